@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -55,50 +55,123 @@ pub fn solve_a() -> Result<u64> {
     Ok(gamma * epsilon)
 }
 
-pub fn solve_b() -> Result<u64> {
-    let input = include_str!("../input");
-    let width = input.lines().next().unwrap().len();
-
-    let dec = input
-        .lines()
-        .map(|line| u64::from_str_radix(line, 2))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    let oxygen = (0..width).rev().fold(dec.clone(), |mut candidates, i| {
-        let nnums = candidates.len();
+/// Which bit to keep when both halves of the candidate set are the same size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    Zero,
+    One,
+}
 
-        if nnums == 1 {
-            return candidates;
+impl TieBreak {
+    fn bit(self) -> usize {
+        match self {
+            TieBreak::Zero => 0,
+            TieBreak::One => 1,
         }
+    }
+}
 
-        let ones_cnt = candidates.iter().filter(|&x| x >> i & 1 == 1).count();
-        let target = if ones_cnt >= (nnums - ones_cnt) { 1 } else { 0 };
-
-        candidates.retain(|&x| x >> i & 1 == target);
-
-        candidates
-    });
+/// Numbers sorted once, so every number sharing a prefix sits in one
+/// contiguous range. The bit criteria narrow that range with a binary search
+/// per bit instead of touching the values again.
+#[derive(Debug)]
+pub struct SortedNums {
+    nums: Vec<u64>,
+    width: usize,
+}
 
-    assert_eq!(oxygen.len(), 1);
-    let oxygen = oxygen[0];
+impl SortedNums {
+    pub fn new(mut nums: Vec<u64>, width: usize) -> SortedNums {
+        nums.sort_unstable();
+        SortedNums { nums, width }
+    }
 
-    let co2 = (0..width).rev().fold(dec, |mut candidates, i| {
-        let nnums = candidates.len();
+    /// Follows the most common bit (or least common if `most_common` is false)
+    /// at each position, using `tie` when both bits are equally common.
+    pub fn rating(&self, most_common: bool, tie: TieBreak) -> Option<u64> {
+        let (mut lo, mut hi) = (0, self.nums.len());
+        if lo == hi {
+            return None;
+        }
 
-        if nnums == 1 {
-            return candidates;
+        for i in (0..self.width).rev() {
+            // numbers in [lo, hi) share their higher bits, so those with bit i
+            // clear come first
+            let mid = lo + self.nums[lo..hi].partition_point(|x| x >> i & 1 == 0);
+            let (zeros, ones) = (mid - lo, hi - mid);
+
+            let b = if zeros == 0 {
+                1
+            } else if ones == 0 {
+                0
+            } else if zeros == ones {
+                tie.bit()
+            } else {
+                ((ones > zeros) == most_common) as usize
+            };
+
+            if b == 0 {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
         }
 
-        let ones_cnt = candidates.iter().filter(|&x| x >> i & 1 == 1).count();
-        let target = if ones_cnt >= (nnums - ones_cnt) { 0 } else { 1 };
+        Some(self.nums[lo])
+    }
+}
+
+/// Returns the (oxygen generator, CO2 scrubber) ratings. `nums` is sorted in
+/// place and shared by both searches.
+pub fn life_support(
+    nums: Vec<u64>,
+    width: usize,
+    oxygen_tie: TieBreak,
+    co2_tie: TieBreak,
+) -> Option<(u64, u64)> {
+    let sorted = SortedNums::new(nums, width);
+
+    Some((
+        sorted.rating(true, oxygen_tie)?,
+        sorted.rating(false, co2_tie)?,
+    ))
+}
 
-        candidates.retain(|&x| x >> i & 1 == target);
+pub fn solve_b() -> Result<u64> {
+    let input = include_str!("../input");
+    let width = input.lines().next().unwrap().len();
 
-        candidates
-    });
+    let dec = input
+        .lines()
+        .map(|line| u64::from_str_radix(line, 2))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    assert_eq!(co2.len(), 1);
-    let co2 = co2[0];
+    let (oxygen, co2) = life_support(dec, width, TieBreak::One, TieBreak::Zero)
+        .ok_or_else(|| anyhow!("no diagnostic numbers"))?;
 
     Ok(oxygen * co2)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_life_support() {
+        assert_eq!(life_support(vec![], 5, TieBreak::One, TieBreak::Zero), None);
+        assert_eq!(
+            life_support(vec![0b10, 0b01], 2, TieBreak::Zero, TieBreak::One),
+            Some((0b01, 0b10))
+        );
+
+        let dec = include_str!("../example")
+            .lines()
+            .map(|line| u64::from_str_radix(line, 2).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            life_support(dec, 5, TieBreak::One, TieBreak::Zero),
+            Some((23, 10))
+        );
+    }
+}
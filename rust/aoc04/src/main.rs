@@ -1,4 +1,5 @@
-use std::convert::TryInto;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use anyhow::{anyhow, Result};
 
@@ -16,64 +17,384 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-const BOARD_SIZE: usize = 25;
-const BOARD_DIM: usize = 5;
+/// Ways a board can be completed. Standard bingo only counts rows and columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WinRule {
+    Row,
+    Column,
+    Diagonal,
+    FourCorners,
+    FullCard,
+}
+
+impl WinRule {
+    /// Flat cell indices of every line that satisfies this rule on a `dim`×`dim` board.
+    fn lines(self, dim: usize) -> Vec<Vec<usize>> {
+        match self {
+            WinRule::Row => (0..dim)
+                .map(|i| (0..dim).map(|j| i * dim + j).collect())
+                .collect(),
+            WinRule::Column => (0..dim)
+                .map(|j| (0..dim).map(|i| i * dim + j).collect())
+                .collect(),
+            WinRule::Diagonal => vec![
+                (0..dim).map(|i| i * dim + i).collect(),
+                (0..dim).map(|i| i * dim + (dim - 1 - i)).collect(),
+            ],
+            WinRule::FourCorners => {
+                let mut corners = vec![0, dim - 1, (dim - 1) * dim, dim * dim - 1];
+                corners.dedup();
+                vec![corners]
+            }
+            WinRule::FullCard => vec![(0..dim * dim).collect()],
+        }
+    }
+}
+
+pub const STANDARD_RULES: &[WinRule] = &[WinRule::Row, WinRule::Column];
 
-#[derive(Debug)]
-struct Board {
-    nums: [i32; BOARD_SIZE],
-    row_marked: [i32; BOARD_DIM],
-    col_marked: [i32; BOARD_DIM],
+#[derive(Debug, Clone)]
+pub struct Board {
+    dim: usize,
+    nums: Vec<i32>,
+    marked: Vec<bool>,
+    unmarked: i64,
+    row_marked: Vec<usize>,
+    col_marked: Vec<usize>,
+    diag_marked: [usize; 2],
+    corners_marked: usize,
+    total_marked: usize,
 }
 
 impl Board {
-    fn from_lines(lines: &[&str]) -> Result<Board> {
-        let nums: [i32; BOARD_SIZE] = lines
-            .iter()
-            .skip(1)
-            .flat_map(|l| l.trim().split_whitespace().map(|x| x.parse::<i32>()))
-            .collect::<Result<Vec<i32>, _>>()?
-            .as_slice()
-            .try_into()?;
+    pub fn from_lines(lines: &[&str]) -> Result<Board> {
+        let dim = lines.len();
 
-        let row_marked = [0i32; BOARD_DIM];
-        let col_marked = [0i32; BOARD_DIM];
+        let mut nums = Vec::with_capacity(dim * dim);
+        for l in lines {
+            let start = nums.len();
+            for x in l.split_whitespace() {
+                nums.push(x.parse::<i32>()?);
+            }
+            if nums.len() - start != dim {
+                return Err(anyhow!(
+                    "expected {} numbers per row, found {}",
+                    dim,
+                    nums.len() - start
+                ));
+            }
+        }
+
+        let unmarked = nums.iter().map(|&x| x as i64).sum();
 
         Ok(Board {
+            dim,
+            marked: vec![false; nums.len()],
+            unmarked,
             nums,
-            row_marked,
-            col_marked,
+            row_marked: vec![0; dim],
+            col_marked: vec![0; dim],
+            diag_marked: [0; 2],
+            corners_marked: 0,
+            total_marked: 0,
+        })
+    }
+
+    fn is_corner(&self, i: usize, j: usize) -> bool {
+        (i == 0 || i == self.dim - 1) && (j == 0 || j == self.dim - 1)
+    }
+
+    fn ncorners(&self) -> usize {
+        if self.dim == 1 {
+            1
+        } else {
+            4
+        }
+    }
+
+    fn mark(&mut self, ix: usize) {
+        if self.marked[ix] {
+            return;
+        }
+        let i = ix / self.dim;
+        let j = ix % self.dim;
+
+        self.marked[ix] = true;
+        self.unmarked -= self.nums[ix] as i64;
+
+        self.row_marked[i] += 1;
+        self.col_marked[j] += 1;
+        if i == j {
+            self.diag_marked[0] += 1;
+        }
+        if i + j == self.dim - 1 {
+            self.diag_marked[1] += 1;
+        }
+        if self.is_corner(i, j) {
+            self.corners_marked += 1;
+        }
+        self.total_marked += 1;
+    }
+
+    pub fn update(&mut self, n: i32) {
+        for ix in 0..self.nums.len() {
+            if self.nums[ix] == n {
+                self.mark(ix);
+            }
+        }
+    }
+
+    pub fn has_won(&self, rules: &[WinRule]) -> bool {
+        rules.iter().any(|rule| match rule {
+            WinRule::Row => self.row_marked.contains(&self.dim),
+            WinRule::Column => self.col_marked.contains(&self.dim),
+            WinRule::Diagonal => self.diag_marked.contains(&self.dim),
+            WinRule::FourCorners => self.corners_marked == self.ncorners(),
+            WinRule::FullCard => self.total_marked == self.nums.len(),
         })
     }
 
-    fn update(&mut self, n: i32) {
-        for (ix, v) in self.nums.iter_mut().enumerate() {
-            if *v != n {
-                continue;
+    pub fn sum_unmarked(&self) -> i64 {
+        self.unmarked
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Win {
+    pub turn: usize,
+    pub num: i32,
+    pub score: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardOutcome {
+    pub board: usize,
+    pub win: Option<Win>,
+}
+
+/// Result of playing a full game: winners in the order they won (ties within a
+/// turn broken by board index), followed by the boards that never won.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    pub outcomes: Vec<BoardOutcome>,
+}
+
+impl Timeline {
+    pub fn first_win(&self) -> Option<&BoardOutcome> {
+        self.outcomes.first().filter(|o| o.win.is_some())
+    }
+
+    pub fn last_win(&self) -> Option<&BoardOutcome> {
+        self.outcomes.iter().rev().find(|o| o.win.is_some())
+    }
+}
+
+/// (board, cell) positions of each number. Numbers from zero up to a few times
+/// the number of cells are looked up directly by value, with the positions of
+/// number `v` stored at `cells[starts[v]..starts[v + 1]]`; anything else, e.g.
+/// negative or very large numbers, falls back to a map.
+#[derive(Debug, Clone, Default)]
+struct CellIndex {
+    starts: Vec<usize>,
+    cells: Vec<(usize, usize)>,
+    sparse: HashMap<i32, Vec<(usize, usize)>>,
+}
+
+impl CellIndex {
+    fn new(boards: &[Board]) -> CellIndex {
+        let ncells = boards.iter().map(|b| b.nums.len()).sum::<usize>();
+        let limit = 4 * ncells + 256;
+        let dense = |n: i32| usize::try_from(n).ok().filter(|&v| v < limit);
+
+        // count each number, then lay the positions out in one vector
+        let mut counts = vec![];
+        for &n in boards.iter().flat_map(|b| b.nums.iter()) {
+            if let Some(v) = dense(n) {
+                if counts.len() <= v {
+                    counts.resize(v + 1, 0);
+                }
+                counts[v] += 1;
             }
-            let i = ix / BOARD_DIM;
-            let j = ix % BOARD_DIM;
+        }
 
-            *v = -1;
+        let mut starts = Vec::with_capacity(counts.len() + 1);
+        starts.push(0);
+        for c in counts {
+            starts.push(starts.last().unwrap() + c);
+        }
 
-            self.row_marked[i] += 1;
-            self.col_marked[j] += 1;
+        let mut next = starts.clone();
+        let mut cells = vec![(0, 0); *starts.last().unwrap()];
+        let mut sparse: HashMap<i32, Vec<(usize, usize)>> = HashMap::new();
+        for (bi, board) in boards.iter().enumerate() {
+            for (ci, &n) in board.nums.iter().enumerate() {
+                match dense(n) {
+                    Some(v) => {
+                        cells[next[v]] = (bi, ci);
+                        next[v] += 1;
+                    }
+                    None => sparse.entry(n).or_default().push((bi, ci)),
+                }
+            }
+        }
+
+        CellIndex {
+            starts,
+            cells,
+            sparse,
         }
     }
 
-    fn has_won(&self) -> bool {
-        self.row_marked.contains(&5) | self.col_marked.contains(&5)
+    fn get(&self, n: i32) -> &[(usize, usize)] {
+        match usize::try_from(n) {
+            Ok(v) if v + 1 < self.starts.len() => &self.cells[self.starts[v]..self.starts[v + 1]],
+            _ => self.sparse.get(&n).map_or(&[], |c| c),
+        }
     }
 
-    fn sum_unmarked(&self) -> i64 {
-        self.nums
+    /// Marks every cell holding `n` on `boards`, which must be the boards the
+    /// index was built from or a copy of them.
+    fn mark_all(&self, boards: &mut [Board], n: i32) {
+        for &(bi, ci) in self.get(n) {
+            boards[bi].mark(ci);
+        }
+    }
+}
+
+/// Draw sequence and boards, along with an index from each number to the
+/// (board, cell) positions it appears at so a draw only touches those cells.
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub sequence: Vec<i32>,
+    pub boards: Vec<Board>,
+    index: CellIndex,
+}
+
+impl Game {
+    pub fn new(sequence: Vec<i32>, boards: Vec<Board>) -> Game {
+        let index = CellIndex::new(&boards);
+
+        Game {
+            sequence,
+            boards,
+            index,
+        }
+    }
+
+    /// (board, cell) positions holding `num`.
+    fn cells_with(&self, num: i32) -> &[(usize, usize)] {
+        self.index.get(num)
+    }
+
+    pub fn draw(&mut self, num: i32) {
+        self.index.mark_all(&mut self.boards, num);
+    }
+
+    /// Plays the whole sequence on a copy of the boards, so the game itself is
+    /// left as it was. Boards already won through `draw` are reported as
+    /// winning on the first turn.
+    pub fn play(&self, rules: &[WinRule]) -> Timeline {
+        let (mut outcomes, won) = self.winners(rules, self.boards.len());
+
+        outcomes.extend(
+            won.iter()
+                .enumerate()
+                .filter(|(_, &w)| !w)
+                .map(|(bi, _)| BoardOutcome {
+                    board: bi,
+                    win: None,
+                }),
+        );
+
+        Timeline { outcomes }
+    }
+
+    /// The first board to win, without playing the rest of the game.
+    pub fn first_win(&self, rules: &[WinRule]) -> Option<BoardOutcome> {
+        self.winners(rules, 1).0.into_iter().next()
+    }
+
+    /// Plays on a copy of the boards until `limit` boards have won, returning
+    /// the winners in order and which boards won.
+    fn winners(&self, rules: &[WinRule], limit: usize) -> (Vec<BoardOutcome>, Vec<bool>) {
+        let mut boards = self.boards.clone();
+        let mut won = vec![false; boards.len()];
+        let mut outcomes = Vec::with_capacity(limit);
+
+        for (turn, &num) in self.sequence.iter().enumerate() {
+            self.index.mark_all(&mut boards, num);
+
+            // every board is checked on the first turn, in case some were
+            // already won through `draw`
+            let all = if turn == 0 { 0..boards.len() } else { 0..0 };
+            for bi in all.chain(self.boards_with(num)) {
+                if won[bi] || !boards[bi].has_won(rules) {
+                    continue;
+                }
+                won[bi] = true;
+                outcomes.push(BoardOutcome {
+                    board: bi,
+                    win: Some(Win {
+                        turn,
+                        num,
+                        score: boards[bi].sum_unmarked() * (num as i64),
+                    }),
+                });
+                if outcomes.len() == limit {
+                    return (outcomes, won);
+                }
+            }
+        }
+
+        (outcomes, won)
+    }
+
+    /// Finds a shortest sequence of draws after which board `target` has won and
+    /// no other board has. Returns `None` if no such sequence exists.
+    ///
+    /// Any winning sequence has to contain the unmarked numbers of one of the
+    /// target's winning lines, and marking more numbers can only help the other
+    /// boards, so it is enough to try each line on its own, shortest first.
+    pub fn shortest_win_sequence(&self, target: usize, rules: &[WinRule]) -> Option<Vec<i32>> {
+        let board = self.boards.get(target)?;
+
+        let mut candidates = rules
             .iter()
-            .filter_map(|x| if *x != -1 { Some(*x as i64) } else { None })
-            .sum::<i64>()
+            .flat_map(|rule| rule.lines(board.dim))
+            .map(|line| {
+                let mut nums = line
+                    .into_iter()
+                    .filter(|&ix| !board.marked[ix])
+                    .map(|ix| board.nums[ix])
+                    .collect::<Vec<_>>();
+                nums.sort_unstable();
+                nums.dedup();
+                nums
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|nums| nums.len());
+
+        candidates.into_iter().find(|nums| {
+            let mut boards = self.boards.clone();
+            for &n in nums {
+                self.index.mark_all(&mut boards, n);
+            }
+
+            boards
+                .iter()
+                .enumerate()
+                .all(|(bi, b)| b.has_won(rules) == (bi == target))
+        })
+    }
+
+    /// Boards containing `num`, in board order. A board appears once per cell
+    /// holding the number.
+    fn boards_with(&self, num: i32) -> impl Iterator<Item = usize> + '_ {
+        self.cells_with(num).iter().map(|&(bi, _)| bi)
     }
 }
 
-fn build_game(input: &str) -> Result<(Vec<i32>, Vec<Board>)> {
+pub fn build_game(input: &str) -> Result<Game> {
     let mut iter = input.lines();
     let sequence = iter
         .next()
@@ -82,54 +403,148 @@ fn build_game(input: &str) -> Result<(Vec<i32>, Vec<Board>)> {
         .map(|x| x.parse::<i32>())
         .collect::<Result<Vec<i32>, _>>()?;
 
+    // boards are separated by blank lines and their size is given by the
+    // number of rows
     let board = iter
         .collect::<Vec<_>>()
-        .chunks(6)
-        .fold(vec![], |mut acc, lines| {
-            acc.push(Board::from_lines(lines).unwrap());
-            acc
-        });
+        .split(|l| l.trim().is_empty())
+        .filter(|lines| !lines.is_empty())
+        .map(Board::from_lines)
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok((sequence, board))
+    Ok(Game::new(sequence, board))
 }
 
 pub fn solve_a() -> Result<i64> {
     let input = include_str!("../input");
-    let (sequence, mut boards) = build_game(input)?;
-
-    for num in sequence.iter() {
-        for board in boards.iter_mut() {
-            board.update(*num);
-            if board.has_won() {
-                return Ok(board.sum_unmarked() * (*num as i64));
-            }
-        }
-    }
+    let game = build_game(input)?;
 
-    Ok(0)
+    Ok(game
+        .first_win(STANDARD_RULES)
+        .and_then(|o| o.win)
+        .map_or(0, |w| w.score))
 }
 
 pub fn solve_b() -> Result<i64> {
     let input = include_str!("../input");
-    let (sequence, mut boards) = build_game(input)?;
+    let game = build_game(input)?;
 
-    let mut have_won = vec![];
-    let mut winning_num = vec![];
+    let timeline = game.play(STANDARD_RULES);
 
-    for num in sequence.iter() {
-        for (bi, board) in boards.iter_mut().enumerate() {
-            if have_won.contains(&bi) {
-                continue;
-            }
-            board.update(*num);
-            if board.has_won() {
-                have_won.push(bi);
-                winning_num.push(num);
-            }
+    timeline
+        .last_win()
+        .and_then(|o| o.win)
+        .map(|w| w.score)
+        .ok_or_else(|| anyhow!("no board won"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_win_rules() {
+        let mut board = Board::from_lines(&["1 2 3", "4 5 6", "7 8 9"]).unwrap();
+
+        for n in [1, 5] {
+            board.update(n);
+        }
+        assert!(!board.has_won(STANDARD_RULES));
+        assert!(!board.has_won(&[WinRule::Diagonal]));
+
+        board.update(9);
+        assert!(!board.has_won(STANDARD_RULES));
+        assert!(board.has_won(&[WinRule::Diagonal]));
+
+        for n in [3, 7] {
+            board.update(n);
         }
+        assert!(board.has_won(&[WinRule::FourCorners]));
+        assert!(!board.has_won(&[WinRule::FullCard]));
+        assert_eq!(board.sum_unmarked(), 2 + 4 + 6 + 8);
     }
 
-    let lw_ix = have_won.pop().unwrap();
-    let lw_num = winning_num.pop().unwrap();
-    Ok(boards[lw_ix].sum_unmarked() * (*lw_num as i64))
+    #[test]
+    fn test_timeline() {
+        let game = build_game(include_str!("../example")).unwrap();
+        let timeline = game.play(STANDARD_RULES);
+
+        assert_eq!(timeline.outcomes.len(), game.boards.len());
+        assert_eq!(timeline.first_win().unwrap().board, 2);
+        assert_eq!(timeline.first_win().unwrap().win.unwrap().score, 4512);
+        assert_eq!(
+            game.first_win(STANDARD_RULES).as_ref(),
+            timeline.first_win()
+        );
+        assert_eq!(timeline.last_win().unwrap().board, 1);
+        assert_eq!(timeline.last_win().unwrap().win.unwrap().score, 1924);
+
+        // playing leaves the boards untouched, so a replay gives the same result
+        assert_eq!(game.play(STANDARD_RULES), timeline);
+        assert!(game.boards.iter().all(|b| b.marked.iter().all(|&m| !m)));
+
+        // a board won through `draw` before playing wins on the first turn
+        let mut game = Game::new(
+            vec![5],
+            vec![
+                Board::from_lines(&["1 2", "3 4"]).unwrap(),
+                Board::from_lines(&["5 6", "7 8"]).unwrap(),
+            ],
+        );
+        game.draw(1);
+        game.draw(2);
+        let timeline = game.play(STANDARD_RULES);
+        assert_eq!(
+            timeline.first_win(),
+            Some(&BoardOutcome {
+                board: 0,
+                win: Some(Win {
+                    turn: 0,
+                    num: 5,
+                    score: (3 + 4) * 5
+                }),
+            })
+        );
+        assert_eq!(timeline.outcomes[1].win, None);
+    }
+
+    #[test]
+    fn test_shortest_win_sequence() {
+        let game = Game::new(
+            vec![],
+            vec![
+                Board::from_lines(&["1 2", "3 4"]).unwrap(),
+                Board::from_lines(&["1 5", "6 2"]).unwrap(),
+                Board::from_lines(&["1 2", "7 8"]).unwrap(),
+            ],
+        );
+
+        // 1 and 2 complete a row on boards 0 and 2 at the same time
+        assert_eq!(
+            game.shortest_win_sequence(0, STANDARD_RULES),
+            Some(vec![3, 4])
+        );
+        assert_eq!(
+            game.shortest_win_sequence(0, &[WinRule::Column]),
+            Some(vec![1, 3])
+        );
+        assert_eq!(
+            game.shortest_win_sequence(2, &[WinRule::Row]),
+            Some(vec![7, 8])
+        );
+
+        // numbers outside the directly indexed range
+        let game = Game::new(
+            vec![],
+            vec![Board::from_lines(&["-1 2", "1000000 4"]).unwrap()],
+        );
+        assert_eq!(
+            game.shortest_win_sequence(0, &[WinRule::Column]),
+            Some(vec![-1, 1000000])
+        );
+
+        let board = Board::from_lines(&["1 2", "3 4"]).unwrap();
+        let game = Game::new(vec![], vec![board.clone(), board]);
+        assert_eq!(game.shortest_win_sequence(0, STANDARD_RULES), None);
+    }
 }
@@ -1,7 +1,9 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::Write;
 
-use anyhow::Result;
-use nom::{bytes::complete::tag, sequence::separated_pair, IResult};
+use anyhow::{anyhow, Result};
+use nom::{bytes::complete::tag, combinator::all_consuming, sequence::separated_pair, IResult};
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -17,62 +19,725 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-type Point = (i32, i32);
-type Segment = (Point, Point);
+pub type Point = (i64, i64);
+pub type Segment = (Point, Point);
+
+/// Primitive step along a segment, normalized so that dx > 0, or (0, 1) for
+/// vertical segments. Line arithmetic is done in i128 so that segments can
+/// span the whole i64 range.
+type Direction = (i128, i128);
 
 fn parse_line(s: &str) -> IResult<&str, Segment> {
-    use nom::character::complete::i32;
+    use nom::character::complete::i64;
     separated_pair(
-        separated_pair(i32, tag(","), i32),
+        separated_pair(i64, tag(","), i64),
         tag(" -> "),
-        separated_pair(i32, tag(","), i32),
+        separated_pair(i64, tag(","), i64),
     )(s)
 }
 
-pub fn solve_a() -> Result<usize> {
-    let icnt = include_str!("../input")
-        .lines()
-        .fold(HashMap::new(), |mut acc: HashMap<(i32, i32), i32>, line| {
-            let (_, ((x1, y1), (x2, y2))) = parse_line(line).unwrap();
-            if (x1 == x2) || (y1 == y2) {
-                for i in x1.min(x2)..=x1.max(x2) {
-                    for j in y1.min(y2)..=y1.max(y2) {
-                        *acc.entry((i, j)).or_default() += 1;
+fn parse_input(s: &str) -> Result<Vec<Segment>> {
+    s.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            all_consuming(parse_line)(line)
+                .map(|(_, seg)| seg)
+                .map_err(|_| anyhow!("bad segment on line {}: {:?}", i + 1, line))
+        })
+        .collect()
+}
+
+fn is_axis_aligned(&((x1, y1), (x2, y2)): &Segment) -> bool {
+    (x1 == x2) || (y1 == y2)
+}
+
+/// How segments that are not horizontal, vertical or at 45° are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Raster {
+    /// Reject them with an error.
+    Strict,
+    /// Cover only the lattice points that lie exactly on the segment.
+    Lattice,
+    /// Cover the cells chosen by Bresenham's line algorithm.
+    Bresenham,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn direction(&((x1, y1), (x2, y2)): &Segment) -> Direction {
+    let (dx, dy) = (x2 as i128 - x1 as i128, y2 as i128 - y1 as i128);
+    let g = gcd(dx, dy).max(1);
+
+    match (dx / g, dy / g) {
+        (0, 0) => (0, 1),
+        (dx, dy) if dx < 0 || (dx == 0 && dy < 0) => (-dx, -dy),
+        d => d,
+    }
+}
+
+/// Horizontal, vertical and 45° lines, which step from cell to neighboring cell.
+fn is_grid_direction(dir: Direction) -> bool {
+    dir.0 <= 1 && dir.1.abs() <= 1
+}
+
+fn bresenham(&((x1, y1), (x2, y2)): &Segment) -> Vec<Point> {
+    let (x1, y1, x2, y2) = (x1 as i128, y1 as i128, x2 as i128, y2 as i128);
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let mut err = dx + dy;
+    let (mut x, mut y) = (x1, y1);
+    let mut cells = vec![];
+
+    loop {
+        cells.push((x as i64, y as i64));
+        if x == x2 && y == y2 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+
+    cells
+}
+
+/// `dy*x - dx*y` is constant along a line, so together with the direction it
+/// identifies the line a point lies on. This can only overflow for very steep
+/// lattice lines far from the origin.
+fn line_key(dir: Direction, (x, y): Point) -> Option<i128> {
+    dir.1
+        .checked_mul(x as i128)?
+        .checked_sub(dir.0.checked_mul(y as i128)?)
+}
+
+/// Position of a lattice point along a line of the given direction. Since the
+/// direction is primitive, consecutive lattice points differ by one.
+fn param(dir: Direction, (x, y): Point) -> i128 {
+    match dir.0 {
+        0 => y as i128,
+        1 => x as i128,
+        dx => (x as i128).div_euclid(dx),
+    }
+}
+
+/// Where two lines with different directions cross: `Ok(None)` if they cross
+/// between lattice points or outside the i64 range, and an error if the exact
+/// answer overflows.
+fn intersect(d1: Direction, k1: i128, d2: Direction, k2: i128) -> Result<Option<Point>> {
+    // d.1 * x - d.0 * y = k
+    let cross =
+        |a: i128, b: i128, c: i128, d: i128| a.checked_mul(b)?.checked_sub(c.checked_mul(d)?);
+    let overflow = || anyhow!("line crossing overflows");
+
+    let det = cross(d1.1, -d2.0, d2.1, -d1.0).ok_or_else(overflow)?;
+    let xn = cross(k1, -d2.0, k2, -d1.0).ok_or_else(overflow)?;
+    let yn = cross(d1.1, k2, d2.1, k1).ok_or_else(overflow)?;
+
+    let (x, y) = match det {
+        0 => return Ok(None),
+        // lines in grid directions, which avoid the slow i128 division
+        1 | -1 => (xn * det, yn * det),
+        _ if xn % det != 0 || yn % det != 0 => return Ok(None),
+        _ => (xn / det, yn / det),
+    };
+
+    Ok(i64::try_from(x).ok().zip(i64::try_from(y).ok()))
+}
+
+/// Maximal runs `(start, end, count)` of a line, with inclusive ends, over
+/// which the number of covering segments is constant and non-zero. `origin`
+/// is any lattice point on the line, used to map positions back to points.
+#[derive(Debug)]
+struct Line {
+    dir: Direction,
+    key: i128,
+    origin: Point,
+    runs: Vec<(i128, i128, u32)>,
+}
+
+impl Line {
+    fn new(dir: Direction, key: i128, origin: Point, intervals: &[(i128, i128)]) -> Line {
+        let mut events = intervals
+            .iter()
+            .flat_map(|&(a, b)| [(a, 1i64), (b + 1, -1)])
+            .collect::<Vec<_>>();
+        events.sort_unstable();
+
+        let mut runs = vec![];
+        let mut count = 0;
+        let mut ix = 0;
+
+        while ix < events.len() {
+            let pos = events[ix].0;
+            while ix < events.len() && events[ix].0 == pos {
+                count += events[ix].1;
+                ix += 1;
+            }
+            if count > 0 && ix < events.len() {
+                runs.push((pos, events[ix].0 - 1, count as u32));
+            }
+        }
+
+        Line {
+            dir,
+            key,
+            origin,
+            runs,
+        }
+    }
+
+    /// Point at position `t`, which must lie on one of the line's segments.
+    fn point(&self, t: i128) -> Point {
+        let dt = t - param(self.dir, self.origin);
+        (
+            (self.origin.0 as i128 + dt * self.dir.0) as i64,
+            (self.origin.1 as i128 + dt * self.dir.1) as i64,
+        )
+    }
+
+    fn count_at(&self, t: i128) -> u32 {
+        let ix = self.runs.partition_point(|r| r.1 < t);
+        match self.runs.get(ix) {
+            Some(&(a, _, c)) if a <= t => c,
+            _ => 0,
+        }
+    }
+}
+
+/// Coverage of the plane by a set of segments, stored per line rather than per
+/// cell. Overlaps along a shared line come from a sweep over that line, and
+/// overlaps between lines of different directions can only happen where those
+/// lines cross. Segments rasterized with [`Raster::Bresenham`] don't lie on a
+/// single line and are kept as individual cells.
+///
+/// Crossings are found by looking up, for each covered stretch of a line, the
+/// lines of every other direction whose keys fall in the range that stretch
+/// passes through, so only pairs of lines that cross inside a covered stretch
+/// are examined. With L lines and P such pairs this costs O(L log L + P log P).
+/// P is at most L², and gets close to it only when long segments are densely
+/// packed, in which case most of those pairs are real overlaps anyway.
+#[derive(Debug)]
+pub struct VentMap {
+    lines: Vec<Line>,
+    index: BTreeMap<(Direction, i128), usize>,
+    directions: Vec<Direction>,
+    crossings: Vec<(Point, usize)>,
+    cells: Vec<(Point, u32)>,
+}
+
+impl VentMap {
+    pub fn new(segments: &[Segment], raster: Raster) -> Result<VentMap> {
+        let mut grouped: BTreeMap<_, (Point, Vec<(i128, i128)>)> = BTreeMap::new();
+        let mut cells = vec![];
+
+        for seg in segments {
+            let dir = direction(seg);
+
+            if !is_grid_direction(dir) {
+                match raster {
+                    Raster::Strict => {
+                        let ((x1, y1), (x2, y2)) = seg;
+                        return Err(anyhow!(
+                            "segment {},{} -> {},{} is not horizontal, vertical or diagonal",
+                            x1,
+                            y1,
+                            x2,
+                            y2
+                        ));
+                    }
+                    Raster::Lattice => (),
+                    Raster::Bresenham => {
+                        cells.extend(bresenham(seg));
+                        continue;
+                    }
+                }
+            }
+
+            let (t1, t2) = (param(dir, seg.0), param(dir, seg.1));
+            let key = line_key(dir, seg.0).ok_or_else(|| {
+                let ((x1, y1), (x2, y2)) = seg;
+                anyhow!("segment {},{} -> {},{} is too steep", x1, y1, x2, y2)
+            })?;
+            grouped
+                .entry((dir, key))
+                .or_insert_with(|| (seg.0, vec![]))
+                .1
+                .push((t1.min(t2), t1.max(t2)));
+        }
+
+        let lines = grouped
+            .iter()
+            .map(|(&(dir, key), (origin, intervals))| Line::new(dir, key, *origin, intervals))
+            .collect::<Vec<_>>();
+
+        let index = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| ((l.dir, l.key), i))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut directions = lines.iter().map(|l| l.dir).collect::<Vec<_>>();
+        directions.dedup();
+
+        // lines come out of `grouped` sorted by direction and then key, so each
+        // direction's lines form one slice that can be binary searched by key
+        let mut groups = vec![];
+        let mut start = 0;
+        for group in lines.chunk_by(|a, b| a.dir == b.dir) {
+            groups.push((start, group));
+            start += group.len();
+        }
+
+        let mut crossings = vec![];
+        for (i, l1) in lines.iter().enumerate() {
+            // consecutive runs form one covered stretch
+            let mut spans: Vec<(i128, i128)> = vec![];
+            for &(a, b, _) in l1.runs.iter() {
+                match spans.last_mut() {
+                    Some(last) if last.1 + 1 == a => last.1 = b,
+                    _ => spans.push((a, b)),
+                }
+            }
+
+            for &(start, group) in groups.iter().filter(|g| g.1[0].dir > l1.dir) {
+                let d2 = group[0].dir;
+                for &(a, b) in spans.iter() {
+                    // the key of the d2 line through a point changes
+                    // monotonically along l1
+                    let key_at =
+                        |t| line_key(d2, l1.point(t)).ok_or_else(|| anyhow!("line key overflows"));
+                    let (ka, kb) = (key_at(a)?, key_at(b)?);
+
+                    let lo = group.partition_point(|l| l.key < ka.min(kb));
+                    let hi = group.partition_point(|l| l.key <= ka.max(kb));
+                    for (j, l2) in group[lo..hi].iter().enumerate() {
+                        // crossings inside a span are covered by l1
+                        if let Some(p) = intersect(l1.dir, l1.key, d2, l2.key)? {
+                            if l2.count_at(param(d2, p)) > 0 {
+                                crossings.push((p, i));
+                                crossings.push((p, start + lo + j));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        crossings.sort_unstable();
+        crossings.dedup();
+
+        cells.sort_unstable();
+        let cells = cells
+            .chunk_by(|a, b| a == b)
+            .map(|group| (group[0], group.len() as u32))
+            .collect();
+
+        Ok(VentMap {
+            lines,
+            index,
+            directions,
+            crossings,
+            cells,
+        })
+    }
+
+    fn line_count_at(&self, p: Point) -> u32 {
+        self.directions
+            .iter()
+            .filter_map(|&dir| self.index.get(&(dir, line_key(dir, p)?)))
+            .map(|&i| {
+                let l = &self.lines[i];
+                l.count_at(param(l.dir, p))
+            })
+            .sum()
+    }
+
+    /// Number of segments covering the cell at `p`.
+    pub fn count_at(&self, p: Point) -> u32 {
+        let extra = match self.cells.binary_search_by_key(&p, |c| c.0) {
+            Ok(ix) => self.cells[ix].1,
+            Err(_) => 0,
+        };
+
+        self.line_count_at(p) + extra
+    }
+
+    /// Number of cells covered by at least two segments. Lines spanning the
+    /// whole i64 range can cover more cells than fit in a `usize`.
+    pub fn overlaps(&self) -> u128 {
+        let along_lines = self
+            .lines
+            .iter()
+            .flat_map(|l| l.runs.iter())
+            .filter(|r| r.2 > 1)
+            .map(|r| r.1 - r.0 + 1)
+            .sum::<i128>();
+
+        // every crossing is covered at least twice, so it should be counted exactly
+        // once however many of its lines already counted it in an overlapping run
+        let correction = self
+            .crossings
+            .chunk_by(|a, b| a.0 == b.0)
+            .map(|group| {
+                let counted = group
+                    .iter()
+                    .filter(|&&(p, i)| {
+                        let l = &self.lines[i];
+                        l.count_at(param(l.dir, p)) > 1
+                    })
+                    .count();
+                1 - counted as i128
+            })
+            .sum::<i128>();
+
+        let rasterized = self
+            .cells
+            .iter()
+            .filter(|&&(p, c)| {
+                let on_lines = self.line_count_at(p);
+                on_lines < 2 && on_lines + c >= 2
+            })
+            .count();
+
+        (along_lines + correction) as u128 + rasterized as u128
+    }
+
+    /// Cells whose coverage isn't fully described by the runs of a single line:
+    /// crossings between lines and cells of rasterized segments.
+    fn special_points(&self) -> Vec<Point> {
+        let mut points = self
+            .crossings
+            .iter()
+            .map(|c| c.0)
+            .chain(self.cells.iter().map(|c| c.0))
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+        points.dedup();
+        points
+    }
+
+    /// Number of cells for each non-zero coverage count.
+    pub fn histogram(&self) -> BTreeMap<u32, u128> {
+        let mut hist: BTreeMap<u32, i128> = BTreeMap::new();
+
+        for &(a, b, c) in self.lines.iter().flat_map(|l| l.runs.iter()) {
+            *hist.entry(c).or_default() += b - a + 1;
+        }
+
+        // the runs counted special points once per line through them with only
+        // that line's coverage, so replace those entries with the total
+        for p in self.special_points() {
+            for &dir in self.directions.iter() {
+                if let Some(&i) = line_key(dir, p).and_then(|k| self.index.get(&(dir, k))) {
+                    let l = &self.lines[i];
+                    let c = l.count_at(param(l.dir, p));
+                    if c > 0 {
+                        *hist.entry(c).or_default() -= 1;
                     }
                 }
             }
+            *hist.entry(self.count_at(p)).or_default() += 1;
+        }
+
+        hist.into_iter()
+            .filter(|&(_, n)| n > 0)
+            .map(|(c, n)| (c, n as u128))
+            .collect()
+    }
+
+    /// Cells covered by at least `k` segments (and at least one), in sorted order.
+    pub fn cells_at_least(&self, k: u32) -> Vec<Point> {
+        let k = k.max(1);
+
+        let mut points = self
+            .lines
+            .iter()
+            .flat_map(|l| {
+                l.runs
+                    .iter()
+                    .filter(|r| r.2 >= k)
+                    .flat_map(move |&(a, b, _)| (a..=b).map(move |t| l.point(t)))
+            })
+            .chain(
+                self.special_points()
+                    .into_iter()
+                    .filter(|&p| self.count_at(p) >= k),
+            )
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+        points.dedup();
+
+        points
+    }
+
+    /// Rasterizes the coverage counts over the bounding box of all segments.
+    pub fn density_grid(&self) -> Result<DensityGrid> {
+        let corners = self
+            .lines
+            .iter()
+            .flat_map(|l| {
+                l.runs
+                    .iter()
+                    .flat_map(move |&(a, b, _)| [l.point(a), l.point(b)])
+            })
+            .chain(self.cells.iter().map(|c| c.0));
+
+        let (xmin, ymin, xmax, ymax) = corners.fold(
+            (i64::MAX, i64::MAX, i64::MIN, i64::MIN),
+            |(x0, y0, x1, y1), (x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        );
+        if xmin > xmax {
+            return Err(anyhow!("no segments to draw"));
+        }
+
+        let width = usize::try_from(xmax as i128 - xmin as i128 + 1)?;
+        let height = usize::try_from(ymax as i128 - ymin as i128 + 1)?;
+        let size = width
+            .checked_mul(height)
+            .ok_or_else(|| anyhow!("{}x{} grid is too large", width, height))?;
+
+        let mut counts = vec![0u32; size];
+        let mut add = |(x, y): Point, c: u32| {
+            counts[(y as i128 - ymin as i128) as usize * width
+                + (x as i128 - xmin as i128) as usize] += c;
+        };
+
+        for l in self.lines.iter() {
+            for &(a, b, c) in l.runs.iter() {
+                (a..=b).for_each(|t| add(l.point(t), c));
+            }
+        }
+        for &(p, c) in self.cells.iter() {
+            add(p, c);
+        }
 
-            acc
+        Ok(DensityGrid {
+            origin: (xmin, ymin),
+            width,
+            height,
+            counts,
         })
-        .values()
-        .filter(|&&v| v > 1)
-        .count();
+    }
+}
+
+/// Coverage counts for every cell of a rectangle, row by row, with `origin`
+/// the point drawn at the top left.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub origin: Point,
+    pub width: usize,
+    pub height: usize,
+    pub counts: Vec<u32>,
+}
+
+impl DensityGrid {
+    fn max_count(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0).max(1)
+    }
+
+    /// Writes the grid as a binary PGM, with brightness proportional to coverage.
+    pub fn write_pgm(&self, w: &mut impl Write) -> Result<()> {
+        let max = self.max_count() as u64;
+        write!(w, "P5\n{} {}\n255\n", self.width, self.height)?;
+
+        let pixels = self
+            .counts
+            .iter()
+            .map(|&c| (c as u64 * 255 / max) as u8)
+            .collect::<Vec<_>>();
+        w.write_all(&pixels)?;
 
-    Ok(icnt)
+        Ok(())
+    }
+
+    /// Writes the grid as a binary PPM using a black-red-yellow-white heat scale.
+    pub fn write_ppm(&self, w: &mut impl Write) -> Result<()> {
+        let max = self.max_count() as f64;
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+
+        let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let pixels = self
+            .counts
+            .iter()
+            .flat_map(|&c| {
+                let v = 3.0 * c as f64 / max;
+                [channel(v), channel(v - 1.0), channel(v - 2.0)]
+            })
+            .collect::<Vec<_>>();
+        w.write_all(&pixels)?;
+
+        Ok(())
+    }
+}
+
+pub fn count_overlaps(segments: &[Segment], raster: Raster) -> Result<usize> {
+    let n = VentMap::new(segments, raster)?.overlaps();
+    usize::try_from(n).map_err(|_| anyhow!("{} overlapping cells don't fit in a usize", n))
+}
+
+pub fn solve_a() -> Result<usize> {
+    let segments = parse_input(include_str!("../input"))?
+        .into_iter()
+        .filter(is_axis_aligned)
+        .collect::<Vec<_>>();
+
+    count_overlaps(&segments, Raster::Strict)
 }
 
 pub fn solve_b() -> Result<usize> {
-    let icnt = include_str!("../input")
-        .lines()
-        .fold(HashMap::new(), |mut acc: HashMap<(i32, i32), i32>, line| {
-            let (_, ((x1, y1), (x2, y2))) = parse_line(line).unwrap();
-            let dx = if x1 == x2 { 0 } else { (x2 - x1).signum() };
-            let dy = if y1 == y2 { 0 } else { (y2 - y1).signum() };
-
-            let npts = (x2 - x1).abs().max((y2 - y1).abs());
-            let (mut i, mut j) = (x1, y1);
-
-            for _ in 0..=npts {
-                *acc.entry((i, j)).or_default() += 1;
-                i += dx;
-                j += dy;
+    let segments = parse_input(include_str!("../input"))?;
+
+    count_overlaps(&segments, Raster::Strict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse_input("0,9 -> 5,9\n8,0 -> 0,8x\n9,4 -> 3,4\n").unwrap_err();
+        assert_eq!(err.to_string(), "bad segment on line 2: \"8,0 -> 0,8x\"");
+
+        let crlf = parse_input("0,9 -> 5,9\r\n8,0 -> 0,8\r\n").unwrap();
+        assert_eq!(crlf, vec![((0, 9), (5, 9)), ((8, 0), (0, 8))]);
+    }
+
+    #[test]
+    fn test_example() {
+        let segments = parse_input(include_str!("../example")).unwrap();
+        assert_eq!(count_overlaps(&segments, Raster::Strict).unwrap(), 12);
+    }
+
+    #[test]
+    fn test_arbitrary_slopes() {
+        use std::collections::HashMap;
+
+        let mut seed = 12345u64;
+        let mut rand = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            ((seed >> 33) % 20) as i64
+        };
+        let segments = (0..200)
+            .map(|_| ((rand(), rand()), (rand(), rand())))
+            .collect::<Vec<_>>();
+
+        assert!(count_overlaps(&segments, Raster::Strict).is_err());
+
+        for raster in [Raster::Lattice, Raster::Bresenham] {
+            let mut counts: HashMap<Point, u32> = HashMap::new();
+            for seg in segments.iter() {
+                let cells = match raster {
+                    Raster::Bresenham => bresenham(seg),
+                    _ => {
+                        let ((x1, y1), (x2, y2)) = *seg;
+                        let g = gcd((x2 - x1) as i128, (y2 - y1) as i128).max(1) as i64;
+                        (0..=g)
+                            .map(|i| (x1 + i * (x2 - x1) / g, y1 + i * (y2 - y1) / g))
+                            .collect()
+                    }
+                };
+                for p in cells {
+                    *counts.entry(p).or_default() += 1;
+                }
             }
 
-            acc
-        })
-        .values()
-        .filter(|&&v| v > 1)
-        .count();
+            let vents = VentMap::new(&segments, raster).unwrap();
+            assert!(counts.iter().all(|(&p, &c)| vents.count_at(p) == c));
+            assert_eq!(
+                vents.overlaps(),
+                counts.values().filter(|&&c| c > 1).count() as u128
+            );
+
+            let mut hist = BTreeMap::new();
+            for &c in counts.values() {
+                *hist.entry(c).or_insert(0) += 1;
+            }
+            assert_eq!(vents.histogram(), hist);
+        }
+    }
+
+    #[test]
+    fn test_density() {
+        let segments = parse_input(include_str!("../example")).unwrap();
+        let vents = VentMap::new(&segments, Raster::Strict).unwrap();
+
+        let hist = vents.histogram();
+        assert_eq!(hist.values().sum::<u128>(), 39);
+        assert_eq!(hist.range(2..).map(|(_, n)| n).sum::<u128>(), 12);
+        assert_eq!(vents.cells_at_least(3), vec![(4, 4), (6, 4)]);
+
+        let grid = vents.density_grid().unwrap();
+        assert_eq!((grid.width, grid.height), (10, 10));
+        assert_eq!(grid.counts.iter().filter(|&&c| c > 1).count(), 12);
+
+        let mut pgm = vec![];
+        grid.write_pgm(&mut pgm).unwrap();
+        assert!(pgm.starts_with(b"P5\n10 10\n255\n"));
+        assert_eq!(pgm.len(), 13 + 100);
+    }
+
+    #[test]
+    fn test_long_segments() {
+        let n = 1_000_000_000_000;
+        let segments = [
+            ((0, 0), (n, 0)),
+            ((n / 2, 0), (2 * n, 0)),
+            ((1, -n), (1, n)),
+            ((-n, -n), (n, n)),
+        ];
+
+        // shared run of the horizontal lines, plus (0, 0), (1, 0) and (1, 1)
+        assert_eq!(
+            count_overlaps(&segments, Raster::Strict).unwrap(),
+            (n / 2 + 1 + 3) as usize
+        );
+    }
+
+    #[test]
+    fn test_i64_bounds() {
+        let (min, max) = (i64::MIN, i64::MAX);
+
+        let segments = [((0, max - 5), (0, max)), ((0, max - 3), (0, max))];
+        assert_eq!(count_overlaps(&segments, Raster::Strict).unwrap(), 4);
+
+        let segments = [((max - 5, -10), (max, -5)), ((max - 5, -10), (max, -5))];
+        assert_eq!(count_overlaps(&segments, Raster::Strict).unwrap(), 6);
+
+        let segments = [
+            ((min, min + 2), (min + 2, min)),
+            ((min, min), (min + 2, min + 2)),
+        ];
+        assert_eq!(count_overlaps(&segments, Raster::Strict).unwrap(), 1);
+
+        // the whole i64 range twice over, which is more cells than a usize holds
+        let segments = [((min, 0), (max, 0)), ((min, 0), (max, 0))];
+        let vents = VentMap::new(&segments, Raster::Strict).unwrap();
+        assert_eq!(vents.overlaps(), 1u128 << 64);
+        assert!(count_overlaps(&segments, Raster::Strict).is_err());
 
-    Ok(icnt)
+        let segments = [
+            ((min, min), (max, max)),
+            ((min, max), (max, min)),
+            ((0, min), (0, max)),
+        ];
+        let vents = VentMap::new(&segments, Raster::Strict).unwrap();
+        // x + y = -1 on the second diagonal, so the diagonals cross between
+        // lattice points and each meets the vertical line at its own cell
+        assert_eq!(vents.count_at((0, 0)), 2);
+        assert_eq!(vents.count_at((0, -1)), 2);
+        assert_eq!(vents.count_at((max, max)), 1);
+        assert_eq!(vents.overlaps(), 2);
+    }
 }
@@ -1,4 +1,7 @@
-use anyhow::Result;
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use num_bigint::BigUint;
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -14,36 +17,260 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-fn parse_initial_state(s: &str) -> [u64; 9] {
-    s.split(',').fold([0u64; 9], |mut acc, x| {
-        let v = x.parse::<usize>().unwrap();
-        acc[v] += 1;
-        acc
-    })
+/// Lanternfish lifecycle: a fish whose timer hits zero resets to `reset_timer`
+/// and spawns a fish starting at `newborn_timer`. `horizon` is the number of
+/// days to simulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Model {
+    pub reset_timer: usize,
+    pub newborn_timer: usize,
+    pub horizon: u64,
 }
 
-pub fn solve_a() -> Result<u64> {
-    let mut state = parse_initial_state(include_str!("../input"));
+pub const PART_A: Model = Model {
+    reset_timer: 6,
+    newborn_timer: 8,
+    horizon: 80,
+};
+
+pub const PART_B: Model = Model {
+    horizon: 256,
+    ..PART_A
+};
+
+/// Arithmetic needed to evolve fish counts with matrix powers.
+trait Count: Clone {
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+}
+
+impl Count for BigUint {
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Modular {
+    v: u64,
+    m: u64,
+}
+
+impl Count for Modular {
+    fn add(&self, other: &Self) -> Self {
+        let v = ((self.v as u128 + other.v as u128) % self.m as u128) as u64;
+        Modular { v, m: self.m }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let v = ((self.v as u128 * other.v as u128) % self.m as u128) as u64;
+        Modular { v, m: self.m }
+    }
+}
+
+type Matrix<T> = Vec<Vec<T>>;
+
+fn mat_mul<T: Count>(a: &Matrix<T>, b: &Matrix<T>, zero: &T) -> Matrix<T> {
+    let n = a.len();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| (0..n).fold(zero.clone(), |acc, k| acc.add(&a[i][k].mul(&b[k][j]))))
+                .collect()
+        })
+        .collect()
+}
+
+impl Model {
+    /// Number of distinct timer values.
+    fn ntimers(&self) -> usize {
+        self.reset_timer.max(self.newborn_timer) + 1
+    }
 
-    for _ in 0..80 {
+    /// Counts of fish for each timer value.
+    pub fn parse_state(&self, s: &str) -> Result<Vec<u64>> {
+        s.trim()
+            .split(',')
+            .try_fold(vec![0u64; self.ntimers()], |mut acc, x| {
+                let v = x.parse::<usize>()?;
+                *acc.get_mut(v)
+                    .ok_or_else(|| anyhow!("timer {} is out of range", v))? += 1;
+                Ok(acc)
+            })
+    }
+
+    fn step(&self, state: &mut [u64]) -> Result<()> {
+        let spawning = state[0];
         state.rotate_left(1);
-        state[6] += state[8];
+        *state.last_mut().unwrap() = 0;
+
+        for t in [self.reset_timer, self.newborn_timer] {
+            state[t] = state[t]
+                .checked_add(spawning)
+                .ok_or_else(|| anyhow!("population overflowed"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Total population at the start and after each day up to the horizon.
+    pub fn series(&self, initial: &[u64]) -> Result<Vec<u64>> {
+        let mut state = initial.to_vec();
+        // huge horizons overflow long before the end, so only reserve a little
+        let mut populations = Vec::with_capacity(self.horizon.min(1 << 16) as usize + 1);
+
+        for day in 0..=self.horizon {
+            if day > 0 {
+                self.step(&mut state)?;
+            }
+            let total = state
+                .iter()
+                .try_fold(0u64, |acc, &x| acc.checked_add(x))
+                .ok_or_else(|| anyhow!("population overflowed"))?;
+            populations.push(total);
+        }
+
+        Ok(populations)
     }
 
-    let fcnt = state.iter().sum();
+    /// Writes the per-day population as `day,population` CSV rows.
+    pub fn write_csv(&self, initial: &[u64], w: &mut impl Write) -> Result<()> {
+        writeln!(w, "day,population")?;
+        for (day, population) in self.series(initial)?.iter().enumerate() {
+            writeln!(w, "{},{}", day, population)?;
+        }
+
+        Ok(())
+    }
+
+    /// Total population at the horizon, computed from powers of the one-day
+    /// transition matrix so the cost grows with log(horizon). `lift` converts
+    /// plain counts into the number type used for the arithmetic.
+    fn evolve<T: Count>(&self, state: &[u64], lift: impl Fn(u64) -> T) -> T {
+        let n = self.ntimers();
+        let zero = lift(0);
+
+        // new[i] = old[i + 1], and old[0] is added to the reset and newborn timers
+        let mut step = vec![vec![zero.clone(); n]; n];
+        for (i, row) in step.iter_mut().enumerate().take(n - 1) {
+            row[i + 1] = lift(1);
+        }
+        step[self.reset_timer][0] = step[self.reset_timer][0].add(&lift(1));
+        step[self.newborn_timer][0] = step[self.newborn_timer][0].add(&lift(1));
+
+        let mut total = vec![vec![zero.clone(); n]; n];
+        for (i, row) in total.iter_mut().enumerate() {
+            row[i] = lift(1);
+        }
+
+        let mut days = self.horizon;
+        while days > 0 {
+            if days & 1 == 1 {
+                total = mat_mul(&total, &step, &zero);
+            }
+            step = mat_mul(&step, &step, &zero);
+            days >>= 1;
+        }
+
+        total.iter().fold(zero.clone(), |acc, row| {
+            row.iter()
+                .zip(state.iter())
+                .fold(acc, |acc, (e, &s)| acc.add(&e.mul(&lift(s))))
+        })
+    }
+
+    /// Exact population at the horizon.
+    pub fn population_exact(&self, state: &[u64]) -> BigUint {
+        self.evolve(state, BigUint::from)
+    }
+
+    /// Population at the horizon, modulo `modulus`.
+    pub fn population_mod(&self, state: &[u64], modulus: u64) -> Result<u64> {
+        if modulus == 0 {
+            return Err(anyhow!("modulus must be positive"));
+        }
+
+        let count = self.evolve(state, |v| Modular {
+            v: v % modulus,
+            m: modulus,
+        });
+
+        Ok(count.v)
+    }
+}
+
+pub fn solve_a() -> Result<u64> {
+    let state = PART_A.parse_state(include_str!("../input"))?;
+
+    let fcnt = *PART_A.series(&state)?.last().unwrap();
 
     Ok(fcnt)
 }
 
 pub fn solve_b() -> Result<u64> {
-    let mut state = parse_initial_state(include_str!("../input"));
+    let state = PART_B.parse_state(include_str!("../input"))?;
 
-    for _ in 0..256 {
-        state.rotate_left(1);
-        state[6] += state[8];
+    let fcnt = *PART_B.series(&state)?.last().unwrap();
+
+    Ok(fcnt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_evolution() {
+        let model = Model {
+            horizon: 18,
+            ..PART_B
+        };
+        let state = model.parse_state("3,4,3,1,2").unwrap();
+
+        assert_eq!(model.population_exact(&state), BigUint::from(26u64));
+        assert_eq!(
+            PART_B.population_exact(&state),
+            BigUint::from(26984457539u64)
+        );
+        assert_eq!(
+            PART_B.population_mod(&state, 1_000_000_007).unwrap(),
+            984457357
+        );
+        assert!(PART_B.population_mod(&state, 0).is_err());
+
+        let huge = Model {
+            horizon: 1_000_000_000_000,
+            ..PART_A
+        };
+        assert!(huge.series(&state).is_err());
+        assert!(huge.write_csv(&state, &mut vec![]).is_err());
     }
 
-    let fcnt = state.iter().sum();
+    #[test]
+    fn test_custom_lifecycle() {
+        let model = Model {
+            reset_timer: 2,
+            newborn_timer: 4,
+            horizon: 30,
+        };
+        let state = model.parse_state("1,2").unwrap();
 
-    Ok(fcnt)
+        let series = model.series(&state).unwrap();
+        assert_eq!(&series[..6], &[2, 2, 3, 4, 4, 5]);
+        assert_eq!(
+            model.population_exact(&state),
+            BigUint::from(*series.last().unwrap())
+        );
+        assert!(model.parse_state("5").is_err());
+
+        let mut csv = vec![];
+        model.write_csv(&state, &mut csv).unwrap();
+        assert!(String::from_utf8(csv)
+            .unwrap()
+            .starts_with("day,population\n0,2\n1,2\n2,3\n"));
+    }
 }
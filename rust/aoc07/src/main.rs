@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -14,53 +14,337 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-pub fn solve_a() -> Result<i64> {
-    // solution is calculating the median of the starting positions
-    let mut x = include_str!("../input")
-        .split(',')
-        .map(|e| e.parse::<i64>())
-        .collect::<Result<Vec<_>, _>>()?;
+/// A crab and its fuel multiplier: moving it costs `weight` times the usual fuel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crab {
+    pub position: i64,
+    pub weight: i64,
+}
+
+/// A crab that moves on a plane rather than a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneCrab {
+    pub position: (i64, i64),
+    pub weight: i64,
+}
+
+/// Fuel needed for a crab to move a given distance. The total cost over all
+/// crabs must be convex in the target position, which holds for any cost that
+/// is convex and non-decreasing in the distance.
+pub trait CostFunction {
+    fn cost(&self, d: i64) -> i64;
+
+    /// Optimal position found in closed form, for costs that have one.
+    fn optimum(&self, _crabs: &[Crab]) -> Option<i64> {
+        None
+    }
+}
+
+/// One unit of fuel per step.
+#[derive(Debug, Clone, Copy)]
+pub struct Linear;
 
+/// Each step costs one more than the last, so moving `d` costs `d(d+1)/2`.
+#[derive(Debug, Clone, Copy)]
+pub struct Triangular;
+
+/// Smallest position holding at least half of the total weight.
+fn weighted_median(mut x: Vec<(i64, i64)>) -> Option<i64> {
     x.sort_unstable();
 
-    let n = x.len();
+    let total = x.iter().map(|e| e.1).sum::<i64>();
+    let mut acc = 0;
+    x.into_iter().find_map(|(p, w)| {
+        acc += w;
+        if 2 * acc >= total {
+            Some(p)
+        } else {
+            None
+        }
+    })
+}
+
+impl CostFunction for Linear {
+    fn cost(&self, d: i64) -> i64 {
+        d
+    }
+
+    fn optimum(&self, crabs: &[Crab]) -> Option<i64> {
+        // solution is the (weighted) median of the starting positions
+        weighted_median(crabs.iter().map(|c| (c.position, c.weight)).collect())
+    }
+}
+
+impl CostFunction for Triangular {
+    fn cost(&self, d: i64) -> i64 {
+        d * (d + 1) / 2
+    }
+
+    fn optimum(&self, crabs: &[Crab]) -> Option<i64> {
+        // C(a) = w_i * (|x_i - a| + 1) * |x_i - a| / 2
+        // dC(a)/da = w_i * (sgn(a - x_i)/2 + a - x_i) = 0
+        // a = mean(x) - sum(w_i * sgn(a - x_i)) / (2 * sum(w_i))
+        // = mean(x) +/- 0.5
+        let total = crabs.iter().map(|c| c.weight).sum::<i64>();
+        if total <= 0 {
+            return None;
+        }
+
+        let m = (crabs.iter().map(|c| c.position * c.weight).sum::<i64>() as f64) / (total as f64);
+        let lo = (m - 0.5).floor() as i64;
+        let hi = (m + 0.5).ceil() as i64;
 
-    let m = match n {
-        n if n % 2 == 0 => (x[n / 2] + x[(n - 1) / 2]) / 2,
-        _ => x[n / 2],
+        (lo..=hi).min_by_key(|&p| total_fuel(crabs, self, p))
+    }
+}
+
+impl<F: Fn(i64) -> i64> CostFunction for F {
+    fn cost(&self, d: i64) -> i64 {
+        self(d)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alignment {
+    pub position: i64,
+    pub fuel: i64,
+}
+
+pub fn total_fuel(crabs: &[Crab], cost: &impl CostFunction, position: i64) -> i64 {
+    crabs
+        .iter()
+        .map(|c| c.weight * cost.cost((c.position - position).abs()))
+        .sum()
+}
+
+/// Finds the cheapest position to align on and its total fuel. Uses the cost
+/// function's closed form when it has one, otherwise searches for the first
+/// position where the total cost stops decreasing.
+pub fn align(crabs: &[Crab], cost: &impl CostFunction) -> Option<Alignment> {
+    let position = match cost.optimum(crabs) {
+        Some(p) => p,
+        None => {
+            let positions = crabs.iter().map(|c| c.position);
+            let (mut lo, mut hi) = (positions.clone().min()?, positions.max()?);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if total_fuel(crabs, cost, mid + 1) >= total_fuel(crabs, cost, mid) {
+                    hi = mid;
+                } else {
+                    lo = mid + 1;
+                }
+            }
+            lo
+        }
     };
 
-    let c = x.iter().map(|e| (e - m).abs()).sum::<i64>();
+    Some(Alignment {
+        position,
+        fuel: total_fuel(crabs, cost, position),
+    })
+}
 
-    Ok(c)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Manhattan,
+    Euclidean,
 }
 
-pub fn solve_b() -> Result<i64> {
-    // C(a) = (|x_i - a| + 1) * |x_i - a| / 2
-    // dC(a)/da = sgn(a - x)/2 + a - x = 0
-    // a = mean(x) - sgn(a - x) / (2*n)
-    // = mean(x) - (a - x) / (2n *|a - x|)
-    // = mean(x) +/- 0.5
-    let x = include_str!("../input")
-        .split(',')
-        .map(|e| e.parse::<i64>())
-        .collect::<Result<Vec<_>, _>>()?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneAlignment {
+    pub position: (f64, f64),
+    pub fuel: f64,
+}
 
-    let n = x.len();
+const WEISZFELD_TOLERANCE: f64 = 1e-9;
+const WEISZFELD_MAX_ITER: usize = 1000;
 
-    let m = (x.iter().sum::<i64>() as f64) / (n as f64);
-    let mc = m.ceil() as i64;
-    let mf = m.floor() as i64;
+fn distance(metric: Metric, (x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> f64 {
+    match metric {
+        Metric::Manhattan => (x1 - x2).abs() + (y1 - y2).abs(),
+        Metric::Euclidean => (x1 - x2).hypot(y1 - y2),
+    }
+}
+
+/// Approximates the weighted geometric median with Weiszfeld's iteration,
+/// starting from the weighted centroid. Crabs sitting on the current estimate
+/// are left out of the update to avoid dividing by zero.
+fn weiszfeld(crabs: &[PlaneCrab]) -> Option<(f64, f64)> {
+    let points = crabs
+        .iter()
+        .map(|c| ((c.position.0 as f64, c.position.1 as f64), c.weight as f64))
+        .collect::<Vec<_>>();
 
-    let (c1, c2) = x.iter().fold((0, 0), |mut acc, e| {
-        let dxc = (e - mc).abs();
-        let dxf = (e - mf).abs();
-        acc.0 += dxc * (dxc + 1) / 2;
-        acc.1 += dxf * (dxf + 1) / 2;
-        acc
+    let total = points.iter().map(|e| e.1).sum::<f64>();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let mut est = points.iter().fold((0.0, 0.0), |acc, &((x, y), w)| {
+        (acc.0 + w * x / total, acc.1 + w * y / total)
     });
 
-    let c = c1.min(c2);
+    for _ in 0..WEISZFELD_MAX_ITER {
+        let (mut nx, mut ny, mut denom) = (0.0, 0.0, 0.0);
+        for &(p, w) in points.iter() {
+            let d = distance(Metric::Euclidean, p, est);
+            if d < WEISZFELD_TOLERANCE {
+                continue;
+            }
+            nx += w * p.0 / d;
+            ny += w * p.1 / d;
+            denom += w / d;
+        }
+        if denom == 0.0 {
+            break;
+        }
+
+        let next = (nx / denom, ny / denom);
+        let moved = distance(Metric::Euclidean, est, next);
+        est = next;
+        if moved < WEISZFELD_TOLERANCE {
+            break;
+        }
+    }
+
+    Some(est)
+}
+
+/// Finds where crabs on a plane should meet. Manhattan distance separates into
+/// one weighted median per axis, so that position is exact; the Euclidean one is
+/// an approximate geometric median.
+pub fn align_plane(crabs: &[PlaneCrab], metric: Metric) -> Option<PlaneAlignment> {
+    let position = match metric {
+        Metric::Manhattan => {
+            let x = weighted_median(crabs.iter().map(|c| (c.position.0, c.weight)).collect())?;
+            let y = weighted_median(crabs.iter().map(|c| (c.position.1, c.weight)).collect())?;
+            (x as f64, y as f64)
+        }
+        Metric::Euclidean => weiszfeld(crabs)?,
+    };
+
+    let fuel = crabs
+        .iter()
+        .map(|c| {
+            let p = (c.position.0 as f64, c.position.1 as f64);
+            c.weight as f64 * distance(metric, p, position)
+        })
+        .sum();
+
+    Some(PlaneAlignment { position, fuel })
+}
+
+/// Parses an optional `:weight` suffix, defaulting to a weight of one.
+fn split_weight(s: &str) -> Result<(&str, i64)> {
+    match s.split_once(':') {
+        Some((p, w)) => {
+            let w = w.parse::<i64>()?;
+            if w < 0 {
+                return Err(anyhow!("negative weight {}", w));
+            }
+            Ok((p, w))
+        }
+        None => Ok((s, 1)),
+    }
+}
+
+/// Parses a comma-separated line of `position[:weight]` entries.
+pub fn parse_crabs(s: &str) -> Result<Vec<Crab>> {
+    s.trim()
+        .split(',')
+        .map(|e| {
+            let (p, weight) = split_weight(e)?;
+            Ok(Crab {
+                position: p.parse::<i64>()?,
+                weight,
+            })
+        })
+        .collect()
+}
+
+/// Parses one `x,y[:weight]` crab per line.
+pub fn parse_plane_crabs(s: &str) -> Result<Vec<PlaneCrab>> {
+    s.lines()
+        .map(|line| {
+            let (p, weight) = split_weight(line.trim())?;
+            let (x, y) = p
+                .split_once(',')
+                .ok_or_else(|| anyhow!("expected x,y but got {}", p))?;
+            Ok(PlaneCrab {
+                position: (x.parse::<i64>()?, y.parse::<i64>()?),
+                weight,
+            })
+        })
+        .collect()
+}
+
+pub fn solve_a() -> Result<i64> {
+    let crabs = parse_crabs(include_str!("../input"))?;
+
+    let a = align(&crabs, &Linear).ok_or_else(|| anyhow!("no crabs"))?;
+
+    Ok(a.fuel)
+}
+
+pub fn solve_b() -> Result<i64> {
+    let crabs = parse_crabs(include_str!("../input"))?;
+
+    let a = align(&crabs, &Triangular).ok_or_else(|| anyhow!("no crabs"))?;
+
+    Ok(a.fuel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align() {
+        let crabs = parse_crabs(include_str!("../example")).unwrap();
+
+        let expected = [
+            Alignment {
+                position: 2,
+                fuel: 37,
+            },
+            Alignment {
+                position: 5,
+                fuel: 168,
+            },
+        ];
+        assert_eq!(align(&crabs, &Linear), Some(expected[0]));
+        assert_eq!(align(&crabs, &Triangular), Some(expected[1]));
+
+        // same costs without the closed forms
+        assert_eq!(align(&crabs, &|d| d), Some(expected[0]));
+        assert_eq!(align(&crabs, &|d| d * (d + 1) / 2), Some(expected[1]));
+    }
+
+    #[test]
+    fn test_weighted() {
+        let crabs = parse_crabs("0:3,10").unwrap();
+
+        let a = align(&crabs, &Linear).unwrap();
+        assert_eq!((a.position, a.fuel), (0, 10));
+
+        // 3a^2 + 3a + (10 - a)(11 - a) is smallest at a = 2
+        let a = align(&crabs, &Triangular).unwrap();
+        assert_eq!(a, align(&crabs, &|d| d * (d + 1) / 2).unwrap());
+        assert_eq!((a.position, a.fuel), (2, 9 + 36));
+    }
+
+    #[test]
+    fn test_plane() {
+        let crabs = parse_plane_crabs("0,0\n4,0\n0,3:2").unwrap();
+
+        let a = align_plane(&crabs, Metric::Manhattan).unwrap();
+        assert_eq!(a.position, (0.0, 0.0));
+        assert_eq!(a.fuel, 4.0 + 2.0 * 3.0);
 
-    Ok(c)
+        // the geometric median of the corners of a square is its center
+        let square = parse_plane_crabs("0,0\n2,0\n0,2\n2,2").unwrap();
+        let a = align_plane(&square, Metric::Euclidean).unwrap();
+        assert!((a.position.0 - 1.0).abs() < 1e-6 && (a.position.1 - 1.0).abs() < 1e-6);
+        assert!((a.fuel - 4.0 * 2f64.sqrt()).abs() < 1e-6);
+    }
 }
@@ -1,6 +1,5 @@
-use std::collections::HashSet;
-
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use rand::{seq::SliceRandom, Rng};
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -27,94 +26,216 @@ pub fn solve_a() -> Result<usize> {
 }
 
 // segments:
-//    0000
-//   1    5
-//   1    5
-//    3333
-//   2    4
-//   2    4
-//    6666
-//
-//    Given 10 samples that represent each digit, there are the following counts that should appear
-//    0 => 8
-//    1 => 6
-//    2 => 4
-//    3 => 7
-//    4 => 9
-//    5 => 8
-//    6 => 7
+//    aaaa
+//   b    c
+//   b    c
+//    dddd
+//   e    f
+//   e    f
+//    gggg
 //
-//    So we can map segments 1, 2, and 4 uniquely based on counts
-//    segment 0 is the difference between the example of len 3 and 2
-//    segment 5 is the segment with count 8 that is not segment 0
-//    segment 3 is the segment that is in the sample of length 4 that isn't segment 1, 5 or 4
-//    segment 6 is the remaining unassigned segment
+// Patterns are stored as bitmasks with bit i set for segment (or wire) i.
+
+const DIGITS: [u8; 10] = [
+    0b1110111, // abcefg
+    0b0100100, // cf
+    0b1011101, // acdeg
+    0b1101101, // acdfg
+    0b0101110, // bcdf
+    0b1101011, // abdfg
+    0b1111011, // abdefg
+    0b0100101, // acf
+    0b1111111, // abcdefg
+    0b1101111, // abcdfg
+];
+
+/// `wiring[w]` is the segment lit by signal wire `w`.
+pub type Wiring = [u8; 7];
+
+fn parse_pattern(s: &str) -> Result<u8> {
+    s.bytes().try_fold(0u8, |acc, c| {
+        if !(b'a'..=b'g').contains(&c) {
+            return Err(anyhow!("invalid wire {:?} in {}", c as char, s));
+        }
+        let bit = 1 << (c - b'a');
+        if acc & bit != 0 {
+            return Err(anyhow!("repeated wire {:?} in {}", c as char, s));
+        }
+        Ok(acc | bit)
+    })
+}
+
+fn rewire(wiring: &Wiring, pattern: u8) -> u8 {
+    (0..7)
+        .filter(|w| pattern >> w & 1 == 1)
+        .fold(0, |acc, w| acc | 1 << wiring[w])
+}
+
+/// Checks that every pattern can still become a digit given the first `n`
+/// wires of `wiring`: the assigned wires it uses must light segments of the
+/// digit, and the assigned wires it doesn't use must not.
+fn consistent(wiring: &Wiring, n: usize, patterns: &[u8]) -> bool {
+    let assigned = (1u8 << n) - 1;
+
+    patterns.iter().all(|&p| {
+        let on = rewire(wiring, p & assigned);
+        let off = rewire(wiring, !p & assigned);
+        DIGITS
+            .iter()
+            .any(|&d| d.count_ones() == p.count_ones() && on & !d == 0 && off & d == 0)
+    })
+}
+
+fn search(wiring: &mut Wiring, n: usize, used: u8, patterns: &[u8], found: &mut Vec<Wiring>) {
+    if n == 7 {
+        found.push(*wiring);
+        return;
+    }
+
+    for seg in (0..7).filter(|s| used >> s & 1 == 0) {
+        wiring[n] = seg;
+        if consistent(wiring, n + 1, patterns) {
+            search(wiring, n + 1, used | 1 << seg, patterns, found);
+        }
+    }
+}
+
+/// Every wiring under which all of the observed patterns are valid digits.
+pub fn solve_wiring(patterns: &[u8]) -> Vec<Wiring> {
+    let mut found = vec![];
+    search(&mut [0; 7], 0, 0, patterns, &mut found);
+    found
+}
+
+fn decode_digits(wiring: &Wiring, digits: &[u8]) -> u64 {
+    digits.iter().fold(0, |acc, &d| {
+        let seg = rewire(wiring, d);
+        acc * 10 + DIGITS.iter().position(|&x| x == seg).unwrap() as u64
+    })
+}
+
+/// Decodes the output value of a `patterns | digits` entry. The output digits
+/// are used as constraints along with the patterns, and any number of patterns
+/// may be missing as long as every wiring that fits gives the same value.
+pub fn decode_entry(line: &str) -> Result<u64> {
+    let (patterns, digits) = line
+        .split_once('|')
+        .ok_or_else(|| anyhow!("missing '|' in {}", line))?;
+
+    let patterns = patterns
+        .split_whitespace()
+        .map(parse_pattern)
+        .collect::<Result<Vec<_>>>()?;
+    let digits = digits
+        .split_whitespace()
+        .map(parse_pattern)
+        .collect::<Result<Vec<_>>>()?;
+
+    let all = patterns
+        .iter()
+        .chain(digits.iter())
+        .copied()
+        .collect::<Vec<_>>();
+    let wirings = solve_wiring(&all);
+
+    let mut values = wirings.iter().map(|w| decode_digits(w, &digits));
+    let value = values
+        .next()
+        .ok_or_else(|| anyhow!("no wiring is consistent with {}", line))?;
+    if values.any(|v| v != value) {
+        return Err(anyhow!("output of {} is ambiguous", line));
+    }
+
+    Ok(value)
+}
+
+pub fn random_wiring(rng: &mut impl Rng) -> Wiring {
+    let mut wiring = [0, 1, 2, 3, 4, 5, 6];
+    wiring.shuffle(rng);
+    wiring
+}
+
+/// Wire letters, in random order, that light the segments of `digit`.
+fn scramble_digit(wiring: &Wiring, digit: u8, rng: &mut impl Rng) -> String {
+    let mut wires = (0..7u8)
+        .filter(|&w| DIGITS[digit as usize] >> wiring[w as usize] & 1 == 1)
+        .map(|w| (w + b'a') as char)
+        .collect::<Vec<_>>();
+    wires.shuffle(rng);
+    wires.into_iter().collect()
+}
+
+/// Builds a `patterns | digits` entry that shows `digits` on a display wired
+/// by `wiring`, with the ten patterns in random order. The inverse of
+/// [`decode_entry`].
+pub fn scramble(wiring: &Wiring, digits: &[u8], rng: &mut impl Rng) -> Result<String> {
+    if (0..7).any(|s| !wiring.contains(&s)) {
+        return Err(anyhow!("{:?} is not a permutation of the segments", wiring));
+    }
+    if let Some(d) = digits.iter().find(|&&d| d > 9) {
+        return Err(anyhow!("{} is not a digit", d));
+    }
+
+    let mut order = (0..10).collect::<Vec<u8>>();
+    order.shuffle(rng);
+
+    let patterns = order
+        .iter()
+        .map(|&d| scramble_digit(wiring, d, rng))
+        .collect::<Vec<_>>();
+    let outputs = digits
+        .iter()
+        .map(|&d| scramble_digit(wiring, d, rng))
+        .collect::<Vec<_>>();
+
+    Ok(format!("{} | {}", patterns.join(" "), outputs.join(" ")))
+}
 
 pub fn solve_b() -> Result<u64> {
-    let s = include_str!("../input")
-        .lines()
-        .map(|line| {
-            let (patterns, digits) = line.split_once('|').unwrap();
-            let mut patterns = patterns.split_whitespace().collect::<Vec<_>>();
-
-            let mut segments = [0u8; 7];
-            let segcnts = patterns.iter().fold([0u8; 7], |mut acc, pattern| {
-                pattern
-                    .chars()
-                    .for_each(|c| acc[(c as u8 - b'a') as usize] += 1);
-                acc
-            });
-
-            segments[1] = segcnts.iter().position(|&x| x == 6).unwrap() as u8 + b'a';
-            segments[2] = segcnts.iter().position(|&x| x == 4).unwrap() as u8 + b'a';
-            segments[4] = segcnts.iter().position(|&x| x == 9).unwrap() as u8 + b'a';
-
-            patterns.sort_unstable_by_key(|x| x.len());
-
-            let pl3: HashSet<_> = patterns[1].bytes().collect();
-            let pl2: HashSet<_> = patterns[0].bytes().collect();
-            segments[0] = *pl3.difference(&pl2).next().unwrap();
-
-            let cnt8 = segcnts
-                .iter()
-                .enumerate()
-                .filter(|(_, &x)| x == 8)
-                .map(|(i, _)| i as u8 + b'a')
-                .collect::<Vec<_>>();
-            segments[5] = if cnt8[0] == segments[0] {
-                cnt8[1]
-            } else {
-                cnt8[0]
-            };
-
-            segments[3] = patterns[2]
-                .bytes()
-                .find(|&x| (x != segments[1]) && (x != segments[4]) && (x != segments[5]))
-                .unwrap();
-
-            segments[6] = (b'a'..=b'g')
-                .find(|&x| !segments[..5].contains(&x))
-                .unwrap();
-
-            digits
-                .split_whitespace()
-                .map(|d| match d.len() {
-                    2 => 1,
-                    3 => 7,
-                    4 => 4,
-                    7 => 8,
-                    6 if !d.contains(segments[3] as char) => 0,
-                    6 if !d.contains(segments[5] as char) => 6,
-                    6 => 9,
-                    5 if d.contains(segments[1] as char) => 5,
-                    5 if d.contains(segments[4] as char) => 3,
-                    5 => 2,
-                    _ => unreachable!(),
-                })
-                .enumerate()
-                .fold(0u64, |acc, (i, v)| acc + v * 10u64.pow(3 - i as u32))
-        })
-        .sum();
-
-    Ok(s)
+    include_str!("../input").lines().map(decode_entry).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let s = include_str!("../example")
+            .lines()
+            .map(|line| decode_entry(line).unwrap())
+            .sum::<u64>();
+        assert_eq!(s, 61229);
+
+        // the lengths alone give away 1, 7, 4 and 8
+        assert_eq!(decode_entry("| be edb cgeb fdgacbe").unwrap(), 1748);
+    }
+
+    #[test]
+    fn test_scramble_round_trip() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(2021);
+        for _ in 0..100 {
+            let wiring = random_wiring(&mut rng);
+            let digits = (0..4).map(|_| rng.gen_range(0..10)).collect::<Vec<u8>>();
+            let line = scramble(&wiring, &digits, &mut rng).unwrap();
+
+            let value = digits.iter().fold(0, |acc, &d| acc * 10 + d as u64);
+            assert_eq!(decode_entry(&line).unwrap(), value);
+        }
+
+        assert!(scramble(&[0, 0, 1, 2, 3, 4, 5], &[1], &mut rng).is_err());
+        assert!(scramble(&[0, 1, 2, 3, 4, 5, 6], &[10], &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // a five-segment digit could be 2, 3 or 5
+        assert!(decode_entry("| abcde").is_err());
+        // only 7 has three segments
+        assert!(decode_entry("abc abd | ab").is_err());
+        assert!(decode_entry("abh | ab").is_err());
+        assert!(decode_entry("abc").is_err());
+    }
 }
@@ -1,7 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::io::Write;
 
-use anyhow::Result;
-use ndarray::{Array, Array2};
+use anyhow::{anyhow, Result};
+use ndarray::{Array, Array2, Array3, Dimension, Ix2};
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -17,7 +17,26 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-const NEIGHBORS: [(isize, isize); 4] = [(0, 1), (1, 0), (-1, 0), (0, -1)];
+/// Which cells count as neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Cells sharing a face: 4 neighbors in 2D, 6 in 3D.
+    Face,
+    /// Cells sharing at least a corner: 8 neighbors in 2D, 26 in 3D.
+    Vertex,
+}
+
+/// Cells at or above `barrier` are walls that separate basins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub connectivity: Connectivity,
+    pub barrier: u32,
+}
+
+pub const PUZZLE: Options = Options {
+    connectivity: Connectivity::Face,
+    barrier: 9,
+};
 
 const fn add(lhs: usize, rhs: isize) -> Option<usize> {
     if rhs.is_negative() {
@@ -27,103 +46,497 @@ const fn add(lhs: usize, rhs: isize) -> Option<usize> {
     }
 }
 
-pub fn solve_a() -> Result<u64> {
-    let (nrows, grid) =
-        include_str!("../input")
-            .lines()
-            .fold((0usize, vec![]), |(mut n, mut acc), line| {
-                acc.extend(line.chars().map(|c| c.to_digit(10).unwrap()));
-                n += 1;
-                (n, acc)
-            });
-
-    let ncols = grid.len() / nrows;
+fn parse_digits(line: &str, acc: &mut Vec<u32>) -> Result<()> {
+    for c in line.chars() {
+        acc.push(
+            c.to_digit(10)
+                .ok_or_else(|| anyhow!("invalid height {:?}", c))?,
+        );
+    }
+    Ok(())
+}
+
+fn parse_grid(s: &str) -> Result<Array2<u32>> {
+    let (nrows, grid) = s
+        .lines()
+        .try_fold((0usize, vec![]), |(mut n, mut acc), line| {
+            parse_digits(line, &mut acc)?;
+            n += 1;
+            Ok::<_, anyhow::Error>((n, acc))
+        })?;
+
+    let ncols = grid.len().checked_div(nrows).unwrap_or(0);
     let grid = Array::from_shape_vec((nrows, ncols), grid)?;
 
-    let sum = grid.indexed_iter().fold(0u64, |mut acc, ((i, j), v)| {
-        if NEIGHBORS.iter().all(|&(di, dj)| {
-            let (ni, nj) = (
-                add(i, di).unwrap_or(usize::MAX),
-                add(j, dj).unwrap_or(usize::MAX),
-            );
-            v < grid.get((ni, nj)).unwrap_or(&u32::MAX)
-        }) {
-            acc += *v as u64 + 1;
-        }
-        acc
-    });
+    Ok(grid)
+}
 
-    Ok(sum)
+/// Parses a voxel height map given as 2D layers separated by blank lines.
+pub fn parse_volume(s: &str) -> Result<Array3<u32>> {
+    let lines = s.lines().collect::<Vec<_>>();
+    let layers = lines
+        .split(|l| l.trim().is_empty())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>();
+
+    let nrows = layers.first().map_or(0, |l| l.len());
+    let mut volume = vec![];
+    for line in layers.iter().flat_map(|l| l.iter()) {
+        parse_digits(line, &mut volume)?;
+    }
+
+    let ncols = volume.len().checked_div(layers.len() * nrows).unwrap_or(0);
+    let volume = Array::from_shape_vec((layers.len(), nrows, ncols), volume)?;
+
+    Ok(volume)
+}
+
+/// Offsets to every neighbor of a cell in `ndim` dimensions.
+fn neighbor_offsets(ndim: usize, connectivity: Connectivity) -> Vec<Vec<isize>> {
+    let mut offsets = vec![vec![]];
+    for _ in 0..ndim {
+        offsets = offsets
+            .into_iter()
+            .flat_map(|o| {
+                (-1..=1).map(move |d| {
+                    let mut o = o.clone();
+                    o.push(d);
+                    o
+                })
+            })
+            .collect();
+    }
+
+    offsets
+        .into_iter()
+        .filter(|o| {
+            let nonzero = o.iter().filter(|&&d| d != 0).count();
+            match connectivity {
+                Connectivity::Face => nonzero == 1,
+                Connectivity::Vertex => nonzero > 0,
+            }
+        })
+        .collect()
 }
 
-type Graph = HashMap<(usize, usize), HashSet<(usize, usize)>>;
+/// Neighbor step: the change in flat index, and the per-axis changes that
+/// need a bounds check.
+struct Offset {
+    flat: isize,
+    axes: Vec<(usize, isize)>,
+}
 
-fn dfs(g: &Graph, tmp: &mut Vec<(usize, usize)>, i: usize, j: usize, visited: &mut Array2<bool>) {
-    visited[(i, j)] = true;
-    tmp.push((i, j));
+/// Row-major flat indexing over a height map of any dimension. Cells are
+/// visited in order while keeping their coordinates in a cursor, so finding
+/// neighbors needs neither division nor allocation.
+struct Volume {
+    shape: Vec<usize>,
+    offsets: Vec<Offset>,
+}
+
+impl Volume {
+    fn new(shape: &[usize], connectivity: Connectivity) -> Volume {
+        let mut strides = vec![1isize; shape.len()];
+        for k in (0..shape.len().saturating_sub(1)).rev() {
+            strides[k] = strides[k + 1] * shape[k + 1] as isize;
+        }
+
+        let offsets = neighbor_offsets(shape.len(), connectivity)
+            .into_iter()
+            .map(|o| Offset {
+                flat: o.iter().zip(strides.iter()).map(|(d, s)| d * s).sum(),
+                axes: o.into_iter().enumerate().filter(|&(_, d)| d != 0).collect(),
+            })
+            .collect();
+
+        Volume {
+            shape: shape.to_vec(),
+            offsets,
+        }
+    }
 
-    if let Some(neighbors) = g.get(&(i, j)) {
-        for &(ni, nj) in neighbors {
-            if !visited[(ni, nj)] {
-                dfs(g, tmp, ni, nj, visited);
+    /// Coordinates of the first cell.
+    fn cursor(&self) -> Vec<usize> {
+        vec![0; self.shape.len()]
+    }
+
+    /// Moves the cursor to the next cell in row-major order.
+    fn advance(&self, coords: &mut [usize]) {
+        for (c, &n) in coords.iter_mut().zip(self.shape.iter()).rev() {
+            *c += 1;
+            if *c < n {
+                return;
             }
+            *c = 0;
         }
     }
+
+    /// Neighbors of cell `ix`, whose coordinates are `coords`.
+    fn neighbors<'a>(&'a self, ix: usize, coords: &'a [usize]) -> impl Iterator<Item = usize> + 'a {
+        let interior = coords
+            .iter()
+            .zip(self.shape.iter())
+            .all(|(&c, &n)| c > 0 && c + 1 < n);
+
+        self.offsets.iter().filter_map(move |o| {
+            let inside = interior
+                || o.axes
+                    .iter()
+                    .all(|&(k, d)| add(coords[k], d).is_some_and(|x| x < self.shape[k]));
+            if inside {
+                Some((ix as isize + o.flat) as usize)
+            } else {
+                None
+            }
+        })
+    }
 }
 
-pub fn solve_b() -> Result<usize> {
-    let (nrows, grid) =
-        include_str!("../input")
-            .lines()
-            .fold((0usize, vec![]), |(mut n, mut acc), line| {
-                acc.extend(line.chars().map(|c| c.to_digit(10).unwrap()));
-                n += 1;
-                (n, acc)
-            });
-
-    let ncols = grid.len() / nrows;
-    let grid = Array::from_shape_vec((nrows, ncols), grid)?;
+/// Cells lower than all of their neighbors, in row-major order. Walls are
+/// never low points.
+pub fn low_points<D: Dimension>(grid: &Array<u32, D>, opts: &Options) -> Vec<D::Pattern> {
+    let grid = grid.as_standard_layout();
+    let heights = grid.as_slice().unwrap();
+    let volume = Volume::new(grid.shape(), opts.connectivity);
+
+    let mut coords = volume.cursor();
+    let mut points = vec![];
+    for (ix, &v) in heights.iter().enumerate() {
+        if v < opts.barrier && volume.neighbors(ix, &coords).all(|n| v < heights[n]) {
+            let mut p = grid.raw_dim();
+            for (k, &c) in coords.iter().enumerate() {
+                p[k] = c;
+            }
+            points.push(p.into_pattern());
+        }
+        volume.advance(&mut coords);
+    }
+
+    points
+}
+
+/// Union-find over flat cell indices, with path halving and union by size.
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(n: usize) -> DisjointSet {
+        DisjointSet {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+/// Labels every cell below the barrier with the basin it belongs to, numbering
+/// basins in the row-major order of their first cell, and returns the labels
+/// along with the number of basins. Walls get `None`.
+pub fn label_basins<D: Dimension>(
+    grid: &Array<u32, D>,
+    opts: &Options,
+) -> (Array<Option<usize>, D>, usize) {
+    let grid = grid.as_standard_layout();
+    let heights = grid.as_slice().unwrap();
+    let volume = Volume::new(grid.shape(), opts.connectivity);
+    let mut ds = DisjointSet::new(heights.len());
+
+    // joining each cell to its later neighbors covers every edge once
+    let mut coords = volume.cursor();
+    for (ix, &v) in heights.iter().enumerate() {
+        if v < opts.barrier {
+            for n in volume.neighbors(ix, &coords) {
+                if n > ix && heights[n] < opts.barrier {
+                    ds.union(ix, n);
+                }
+            }
+        }
+        volume.advance(&mut coords);
+    }
 
-    // Build the adjacency list
-    let adj = grid.indexed_iter().fold(
-        HashMap::with_capacity(grid.len()),
-        |mut acc: Graph, ((i, j), v)| {
-            if *v == 9 {
-                return acc;
+    let mut root_label = vec![usize::MAX; heights.len()];
+    let mut nbasins = 0;
+    let labels = heights
+        .iter()
+        .enumerate()
+        .map(|(ix, &v)| {
+            if v >= opts.barrier {
+                return None;
             }
+            let root = ds.find(ix);
+            if root_label[root] == usize::MAX {
+                root_label[root] = nbasins;
+                nbasins += 1;
+            }
+            Some(root_label[root])
+        })
+        .collect::<Vec<_>>();
+
+    let labels = Array::from_shape_vec(grid.raw_dim(), labels).unwrap();
+
+    (labels, nbasins)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Basin<P> {
+    /// Lowest cell, the first in row-major order if several share the height.
+    pub low_point: P,
+    /// Cells in row-major order.
+    pub cells: Vec<P>,
+    /// Average height over the basin's cells.
+    pub mean_depth: f64,
+}
+
+impl<P> Basin<P> {
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+/// Every basin of a height map along with the label of each cell, where label
+/// `k` refers to `basins[k]`.
+#[derive(Debug, Clone)]
+pub struct BasinMap<D: Dimension> {
+    pub labels: Array<Option<usize>, D>,
+    pub basins: Vec<Basin<D::Pattern>>,
+}
 
-            for &(di, dj) in NEIGHBORS.iter() {
-                let (ni, nj) = (
-                    add(i, di).unwrap_or(usize::MAX),
-                    add(j, dj).unwrap_or(usize::MAX),
-                );
+const LABEL_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
 
-                if let Some(nv) = grid.get((ni, nj)) {
-                    if *nv != 9 {
-                        acc.entry((i, j)).or_default().insert((ni, nj));
-                    }
+impl<D: Dimension> BasinMap<D> {
+    pub fn new(grid: &Array<u32, D>, opts: &Options) -> BasinMap<D> {
+        let (labels, nbasins) = label_basins(grid, opts);
+
+        let grid = grid.as_standard_layout();
+
+        // basins paired with the height of their current low point
+        let mut basins: Vec<Option<(Basin<D::Pattern>, u32)>> = vec![None; nbasins];
+
+        for ((p, l), &v) in labels.indexed_iter().zip(grid.iter()) {
+            if let Some(l) = *l {
+                let (b, low) = basins[l].get_or_insert_with(|| {
+                    let b = Basin {
+                        low_point: p.clone(),
+                        cells: vec![],
+                        mean_depth: 0.0,
+                    };
+                    (b, v)
+                });
+                if v < *low {
+                    b.low_point = p.clone();
+                    *low = v;
                 }
+                b.cells.push(p);
+                b.mean_depth += v as f64;
             }
+        }
 
-            acc
-        },
-    );
+        let basins = basins
+            .into_iter()
+            .flatten()
+            .map(|(mut b, _)| {
+                b.mean_depth /= b.size() as f64;
+                b
+            })
+            .collect();
 
-    // Now find the connected components
-    let mut visited = Array::from_elem((nrows, ncols), false);
-    let mut ccmp = vec![];
+        BasinMap { labels, basins }
+    }
+}
 
-    for &(i, j) in adj.keys() {
-        if !visited[(i, j)] {
-            let mut tmp = vec![];
-            dfs(&adj, &mut tmp, i, j, &mut visited);
-            ccmp.push(tmp);
+impl BasinMap<Ix2> {
+    /// Writes one character per cell: `#` for walls, otherwise the basin label
+    /// cycling through 0-9, a-z and A-Z.
+    pub fn write_text(&self, w: &mut impl Write) -> Result<()> {
+        for row in self.labels.rows() {
+            let line = row
+                .iter()
+                .map(|l| match l {
+                    Some(l) => LABEL_CHARS[l % LABEL_CHARS.len()],
+                    None => b'#',
+                })
+                .collect::<Vec<_>>();
+            w.write_all(&line)?;
+            writeln!(w)?;
         }
+
+        Ok(())
     }
 
-    ccmp.sort_unstable_by_key(|x| x.len());
+    /// Writes a binary PPM with walls in black and each basin in its own color.
+    pub fn write_ppm(&self, w: &mut impl Write) -> Result<()> {
+        let (nrows, ncols) = self.labels.dim();
+        write!(w, "P6\n{} {}\n255\n", ncols, nrows)?;
+
+        let pixels = self
+            .labels
+            .iter()
+            .flat_map(|l| match l {
+                Some(l) => basin_color(*l),
+                None => [0, 0, 0],
+            })
+            .collect::<Vec<_>>();
+        w.write_all(&pixels)?;
 
-    let x = ccmp.iter().rev().take(3).map(|c| c.len()).product();
+        Ok(())
+    }
+}
+
+/// Spreads labels around the hue circle by the golden ratio so neighboring
+/// labels get distinct colors.
+fn basin_color(label: usize) -> [u8; 3] {
+    let h = (label as f64 * 0.618_033_988_749_895).fract() * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let scale = |v: f64| (55.0 + 200.0 * v) as u8;
+
+    [scale(r), scale(g), scale(b)]
+}
+
+pub fn solve_a() -> Result<u64> {
+    let grid = parse_grid(include_str!("../input"))?;
+
+    let sum = low_points(&grid, &PUZZLE)
+        .into_iter()
+        .map(|p| grid[p] as u64 + 1)
+        .sum();
+
+    Ok(sum)
+}
+
+pub fn solve_b() -> Result<usize> {
+    let grid = parse_grid(include_str!("../input"))?;
+
+    let mut sizes = BasinMap::new(&grid, &PUZZLE)
+        .basins
+        .iter()
+        .map(|b| b.size())
+        .collect::<Vec<_>>();
+    sizes.sort_unstable();
+
+    let x = sizes.iter().rev().take(3).product();
 
     Ok(x)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_basins() {
+        let grid = parse_grid(include_str!("../example")).unwrap();
+        let (labels, nbasins) = label_basins(&grid, &PUZZLE);
+
+        assert_eq!(nbasins, 4);
+        assert_eq!(labels[(0, 0)], Some(0));
+        assert_eq!(labels[(0, 9)], Some(1));
+        assert_eq!(labels[(0, 2)], None);
+    }
+
+    #[test]
+    fn test_basin_map() {
+        let grid = parse_grid(include_str!("../example")).unwrap();
+        let map = BasinMap::new(&grid, &PUZZLE);
+
+        let sizes = map.basins.iter().map(|b| b.size()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![3, 9, 14, 9]);
+        assert_eq!(map.basins[0].low_point, (0, 1));
+        assert_eq!(map.basins[0].cells, vec![(0, 0), (0, 1), (1, 0)]);
+        assert_eq!(map.basins[0].mean_depth, 2.0);
+
+        let mut text = vec![];
+        map.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(text.lines().next(), Some("00###11111"));
+
+        let mut ppm = vec![];
+        map.write_ppm(&mut ppm).unwrap();
+        assert_eq!(ppm.len(), "P6\n10 5\n255\n".len() + 3 * 50);
+    }
+
+    #[test]
+    fn test_options() {
+        let grid = parse_grid(include_str!("../example")).unwrap();
+
+        let vertex = Options {
+            connectivity: Connectivity::Vertex,
+            ..PUZZLE
+        };
+        assert_eq!(label_basins(&grid, &vertex).1, 1);
+
+        // heights of 8 become walls too
+        let low = Options {
+            barrier: 8,
+            ..PUZZLE
+        };
+        let (labels, _) = label_basins(&grid, &low);
+        assert_eq!(labels[(3, 0)], None);
+        assert_eq!(
+            low_points(&grid, &PUZZLE),
+            vec![(0, 1), (0, 9), (2, 2), (4, 6)]
+        );
+    }
+
+    #[test]
+    fn test_volume() {
+        let volume = parse_volume("999\n919\n999\n\n929\n909\n999\n\n999\n999\n993\n").unwrap();
+        assert_eq!(volume.dim(), (3, 3, 3));
+        assert_eq!(low_points(&volume, &PUZZLE), vec![(1, 1, 1), (2, 2, 2)]);
+
+        let map = BasinMap::new(&volume, &PUZZLE);
+        assert_eq!(map.basins.len(), 2);
+        assert_eq!(map.basins[0].cells, vec![(0, 1, 1), (1, 0, 1), (1, 1, 1)]);
+        assert_eq!(map.basins[0].low_point, (1, 1, 1));
+        assert_eq!(map.basins[1].cells, vec![(2, 2, 2)]);
+
+        let vertex = Options {
+            connectivity: Connectivity::Vertex,
+            ..PUZZLE
+        };
+        assert_eq!(label_basins(&volume, &vertex).1, 1);
+    }
+
+    #[test]
+    fn test_long_basin() {
+        // a single winding basin, deep enough to overflow a recursive search
+        let n = 1000;
+        let grid = Array::from_shape_fn((n, n), |(i, j)| match i % 4 {
+            1 if j != n - 1 => 9,
+            3 if j != 0 => 9,
+            _ => 0,
+        });
+        let (labels, nbasins) = label_basins(&grid, &PUZZLE);
+
+        assert_eq!(nbasins, 1);
+        assert_eq!(labels.iter().flatten().count(), n * n / 2 + n / 2);
+    }
+}
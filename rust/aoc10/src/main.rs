@@ -1,4 +1,6 @@
-use anyhow::Result;
+use std::io::BufRead;
+
+use anyhow::{anyhow, Result};
 
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
@@ -14,59 +16,547 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-enum Nav {
+/// Table of opening and closing delimiters. Delimiters can be any non-empty
+/// strings, e.g. `begin`/`end`; at each position the longest one that matches
+/// is taken, and bytes that don't start a delimiter are skipped. A delimiter
+/// that starts or ends with an ASCII letter, digit or underscore only matches
+/// at a word boundary on that side, so `end` isn't found inside `blend` or
+/// `endless`.
+#[derive(Debug, Clone)]
+pub struct Brackets {
+    pairs: Vec<(String, String)>,
+    // delimiters by first byte, longest first
+    by_first: Vec<Vec<Delimiter>>,
+}
+
+#[derive(Debug, Clone)]
+struct Delimiter {
+    token: Token,
+    bytes: Box<[u8]>,
+    word_start: bool,
+    word_end: bool,
+}
+
+impl Delimiter {
+    fn new(token: Token, d: &str) -> Delimiter {
+        let bytes: Box<[u8]> = d.as_bytes().into();
+        Delimiter {
+            token,
+            word_start: is_word(bytes[0]),
+            word_end: is_word(bytes[bytes.len() - 1]),
+            bytes,
+        }
+    }
+
+    /// Whether the delimiter is at the start of `s` and isn't part of a longer
+    /// word, given the byte `prev` before `s`. The first byte is already known
+    /// to match.
+    fn matches(&self, prev: Option<u8>, s: &[u8]) -> bool {
+        let len = self.bytes.len();
+        (len == 1 || s.starts_with(&self.bytes))
+            && !(self.word_start && prev.is_some_and(is_word))
+            && !(self.word_end && s.get(len).copied().is_some_and(is_word))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Open(usize),
+    Close(usize),
+}
+
+/// An opening delimiter: its index in the bracket table and its byte offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Open {
+    pub pair: usize,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Nav {
     Complete,
-    Incomplete(Vec<char>),
-    SyntaxErr(char),
-}
-
-fn check_syntax(s: &str) -> Nav {
-    let mut brackets = vec![];
-
-    for c in s.chars() {
-        match c {
-            '[' => brackets.push(']'),
-            '{' => brackets.push('}'),
-            '(' => brackets.push(')'),
-            '<' => brackets.push('>'),
-            ']' | '}' | ')' | '>' => {
-                if Some(c) != brackets.pop() {
-                    return Nav::SyntaxErr(c);
+    /// Delimiters left open at the end of the line, innermost first.
+    Incomplete(Vec<Open>),
+    /// A closer of pair `pair` at byte `offset` that doesn't match the
+    /// innermost open delimiter, or that has nothing to close.
+    SyntaxErr {
+        pair: usize,
+        offset: usize,
+        open: Option<Open>,
+    },
+}
+
+impl Brackets {
+    pub fn new(pairs: &[(&str, &str)]) -> Result<Brackets> {
+        if pairs.iter().any(|(o, c)| o.is_empty() || c.is_empty()) {
+            return Err(anyhow!("delimiters must not be empty"));
+        }
+
+        // when two delimiters are equal, the later pair and the closer win
+        let mut by_first = vec![Vec::<Delimiter>::new(); 256];
+        for (i, (o, c)) in pairs.iter().enumerate().rev() {
+            by_first[c.as_bytes()[0] as usize].push(Delimiter::new(Token::Close(i), c));
+            by_first[o.as_bytes()[0] as usize].push(Delimiter::new(Token::Open(i), o));
+        }
+        for bucket in by_first.iter_mut() {
+            bucket.sort_by_key(|d| std::cmp::Reverse(d.bytes.len()));
+        }
+
+        Ok(Brackets {
+            pairs: pairs
+                .iter()
+                .map(|&(o, c)| (o.to_string(), c.to_string()))
+                .collect(),
+            by_first,
+        })
+    }
+
+    pub fn standard() -> Brackets {
+        Brackets::new(&[("(", ")"), ("[", "]"), ("{", "}"), ("<", ">")]).unwrap()
+    }
+
+    pub fn opener(&self, pair: usize) -> &str {
+        &self.pairs[pair].0
+    }
+
+    pub fn closer(&self, pair: usize) -> &str {
+        &self.pairs[pair].1
+    }
+
+    /// Longest delimiter at the start of `s`, with its length in bytes. `prev`
+    /// is the byte before `s`, and `s` has to extend one byte past the
+    /// delimiter, or to the end of the line, for the boundary checks.
+    fn token(&self, prev: Option<u8>, s: &[u8]) -> Option<(Token, usize)> {
+        let first = *s.first()?;
+        self.by_first[first as usize]
+            .iter()
+            .find(|d| d.matches(prev, s))
+            .map(|d| (d.token, d.bytes.len()))
+    }
+
+    /// Delimiters in `s` with their byte offsets.
+    fn tokens<'a>(&'a self, s: &'a str) -> impl Iterator<Item = (usize, Token)> + 'a {
+        let s = s.as_bytes();
+        let mut pos = 0;
+
+        std::iter::from_fn(move || {
+            while pos < s.len() {
+                let start = pos;
+                let prev = pos.checked_sub(1).map(|p| s[p]);
+                match self.token(prev, &s[pos..]) {
+                    Some((t, len)) => {
+                        pos += len;
+                        return Some((start, t));
+                    }
+                    None => pos += 1,
+                }
+            }
+            None
+        })
+    }
+}
+
+/// ASCII letters, digits and underscores.
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Syntax check of a single line, fed one delimiter at a time.
+#[derive(Debug, Default)]
+struct LineCheck {
+    stack: Vec<Open>,
+    err: Option<Nav>,
+}
+
+impl LineCheck {
+    fn feed(&mut self, offset: usize, t: Token) {
+        if self.err.is_some() {
+            return;
+        }
+
+        match t {
+            Token::Open(pair) => self.stack.push(Open { pair, offset }),
+            Token::Close(pair) => match self.stack.pop() {
+                Some(o) if o.pair == pair => (),
+                open => self.err = Some(Nav::SyntaxErr { pair, offset, open }),
+            },
+        }
+    }
+
+    fn finish(mut self) -> Nav {
+        if let Some(err) = self.err {
+            err
+        } else if self.stack.is_empty() {
+            Nav::Complete
+        } else {
+            self.stack.reverse();
+            Nav::Incomplete(self.stack)
+        }
+    }
+}
+
+pub fn check_syntax(s: &str, brackets: &Brackets) -> Nav {
+    let mut check = LineCheck::default();
+
+    for (offset, t) in brackets.tokens(s) {
+        check.feed(offset, t);
+        if check.err.is_some() {
+            break;
+        }
+    }
+
+    check.finish()
+}
+
+/// Checks each line of a reader as it is read, without holding whole lines in
+/// memory. Only the tail of a chunk that could still be the start of a
+/// delimiter is kept until more input arrives.
+pub struct SyntaxStream<'a, R> {
+    reader: R,
+    brackets: &'a Brackets,
+    longest: usize,
+    // bytes of the current line not yet scanned, starting at byte `offset`,
+    // and the byte before them
+    pending: Vec<u8>,
+    offset: usize,
+    prev: Option<u8>,
+    check: LineCheck,
+    in_line: bool,
+    done: bool,
+}
+
+pub fn check_stream<R: BufRead>(reader: R, brackets: &Brackets) -> SyntaxStream<'_, R> {
+    let longest = brackets
+        .pairs
+        .iter()
+        .map(|(o, c)| o.len().max(c.len()))
+        .max()
+        .unwrap_or(1);
+
+    SyntaxStream {
+        reader,
+        brackets,
+        longest,
+        pending: vec![],
+        offset: 0,
+        prev: None,
+        check: LineCheck::default(),
+        in_line: false,
+        done: false,
+    }
+}
+
+impl<'a, R: BufRead> SyntaxStream<'a, R> {
+    /// Feeds the pending bytes to the line check. Unless the line has ended,
+    /// stops where a delimiter, or the byte after it, could run past the bytes
+    /// read so far.
+    fn scan(&mut self, line_end: bool) {
+        let mut pos = 0;
+        while pos < self.pending.len() && self.check.err.is_none() {
+            if !line_end && self.pending.len() - pos <= self.longest {
+                break;
+            }
+            let prev = match pos {
+                0 => self.prev,
+                _ => Some(self.pending[pos - 1]),
+            };
+            match self.brackets.token(prev, &self.pending[pos..]) {
+                Some((t, len)) => {
+                    self.check.feed(self.offset + pos, t);
+                    pos += len;
                 }
+                None => pos += 1,
             }
-            _ => (),
         }
+
+        if pos > 0 {
+            self.prev = self.pending.get(pos - 1).copied();
+        }
+        if self.check.err.is_some() {
+            self.pending.clear();
+        } else {
+            self.pending.drain(..pos);
+        }
+        self.offset += pos;
     }
-    if brackets.is_empty() {
-        Nav::Complete
-    } else {
-        Nav::Incomplete(brackets.into_iter().rev().collect())
+
+    fn finish_line(&mut self) -> Nav {
+        self.scan(true);
+        self.pending.clear();
+        self.offset = 0;
+        self.prev = None;
+        self.in_line = false;
+        std::mem::take(&mut self.check).finish()
     }
 }
 
-fn score_completion(x: &[char]) -> u64 {
-    x.iter().fold(0u64, |acc, c| {
+impl<'a, R: BufRead> Iterator for SyntaxStream<'a, R> {
+    type Item = Result<Nav>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+
+            if buf.is_empty() {
+                self.done = true;
+                if self.in_line {
+                    return Some(Ok(self.finish_line()));
+                }
+                break;
+            }
+
+            let newline = buf.iter().position(|&b| b == b'\n');
+            let chunk = &buf[..newline.unwrap_or(buf.len())];
+            // bytes after a syntax error can't change the result
+            if self.check.err.is_none() {
+                self.pending.extend_from_slice(chunk);
+            }
+            let used = newline.map_or(buf.len(), |i| i + 1);
+            self.reader.consume(used);
+            self.in_line = true;
+
+            if newline.is_some() {
+                return Some(Ok(self.finish_line()));
+            }
+            self.scan(false);
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// Insert `text` before byte `offset` of the original line.
+    Insert { offset: usize, text: String },
+    /// Delete the delimiter at byte `offset`.
+    Delete { offset: usize, len: usize },
+    /// Replace the delimiter at byte `offset` with `text`.
+    Substitute {
+        offset: usize,
+        len: usize,
+        text: String,
+    },
+}
+
+/// A balanced version of a line and the edits that produce it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub line: String,
+    pub edits: Vec<Edit>,
+}
+
+struct Repairer<'a> {
+    brackets: &'a Brackets,
+    tokens: Vec<(usize, Token)>,
+    end: usize,
+    // best split of tokens[i..j]: `None` leaves tokens[i] unpaired, `Some(k)`
+    // pairs it with tokens[k]
+    choice: Vec<Vec<Option<usize>>>,
+    edits: Vec<Edit>,
+}
+
+impl<'a> Repairer<'a> {
+    fn len(&self, t: Token) -> usize {
+        match t {
+            Token::Open(p) => self.brackets.opener(p).len(),
+            Token::Close(p) => self.brackets.closer(p).len(),
+        }
+    }
+
+    /// Edits needed to make tokens `a` and `b` an opener and its closer.
+    fn pair_cost(a: Token, b: Token) -> usize {
+        match (a, b) {
+            (Token::Open(t), Token::Close(u)) if t == u => 0,
+            (Token::Close(_), Token::Open(_)) => 2,
+            _ => 1,
+        }
+    }
+
+    fn substitute(&mut self, ix: usize, text: &str) {
+        let (offset, t) = self.tokens[ix];
+        self.edits.push(Edit::Substitute {
+            offset,
+            len: self.len(t),
+            text: text.to_string(),
+        });
+    }
+
+    /// Records the edits for the best split of tokens[i..j], inner groups
+    /// before the edit that closes or deletes their unpaired parent.
+    fn reconstruct(&mut self, i: usize, j: usize) {
+        let mut todo = vec![Step::Balance(i, j)];
+
+        while let Some(step) = todo.pop() {
+            let (i, j) = match step {
+                Step::Balance(i, j) if i < j => (i, j),
+                Step::Balance(..) => continue,
+                Step::Emit(e) => {
+                    self.edits.push(e);
+                    continue;
+                }
+            };
+
+            let (offset, t) = self.tokens[i];
+            match self.choice[i][j] {
+                None => {
+                    let e = match t {
+                        Token::Open(p) => Edit::Insert {
+                            offset: self.tokens.get(j).map_or(self.end, |e| e.0),
+                            text: self.brackets.closer(p).to_string(),
+                        },
+                        Token::Close(_) => Edit::Delete {
+                            offset,
+                            len: self.len(t),
+                        },
+                    };
+                    todo.push(Step::Emit(e));
+                    todo.push(Step::Balance(i + 1, j));
+                }
+                Some(k) => {
+                    let brackets = self.brackets;
+                    match (t, self.tokens[k].1) {
+                        (Token::Open(p), Token::Close(q)) if p == q => (),
+                        (Token::Open(p), _) => self.substitute(k, brackets.closer(p)),
+                        (Token::Close(_), Token::Close(q)) => {
+                            self.substitute(i, brackets.opener(q))
+                        }
+                        (Token::Close(p), Token::Open(_)) => {
+                            self.substitute(i, brackets.opener(p));
+                            self.substitute(k, brackets.closer(p));
+                        }
+                    }
+                    todo.push(Step::Balance(k + 1, j));
+                    todo.push(Step::Balance(i + 1, k));
+                }
+            }
+        }
+    }
+}
+
+/// Pending work while reconstructing a repair.
+enum Step {
+    Balance(usize, usize),
+    Emit(Edit),
+}
+
+/// Most delimiters `repair` takes in one line; its tables grow with the
+/// square of the count and the search with the cube.
+pub const MAX_REPAIR_TOKENS: usize = 1_000;
+
+/// Finds a fewest-edit way to balance a line, counting each inserted, deleted
+/// or substituted delimiter as one edit. Text between delimiters is kept as
+/// is. An unpaired opener is closed just before the delimiter that ends its
+/// enclosing group (or at the end of the line) and an unpaired closer is
+/// deleted.
+///
+/// Takes O(n²) memory and O(n³) time in the number of delimiters n, so lines
+/// with more than `MAX_REPAIR_TOKENS` delimiters are an error.
+pub fn repair(s: &str, brackets: &Brackets) -> Result<Repair> {
+    let tokens = brackets.tokens(s).collect::<Vec<_>>();
+    let n = tokens.len();
+    if n > MAX_REPAIR_TOKENS {
+        return Err(anyhow!(
+            "line has {} delimiters, more than the {} repair handles",
+            n,
+            MAX_REPAIR_TOKENS
+        ));
+    }
+
+    // cost[i][j] is the fewest edits that balance tokens[i..j]
+    let mut cost = vec![vec![0usize; n + 1]; n + 1];
+    let mut choice = vec![vec![None; n + 1]; n + 1];
+
+    for len in 1..=n {
+        for i in 0..=n - len {
+            let j = i + len;
+            let mut best = (cost[i + 1][j] + 1, None);
+            for k in i + 1..j {
+                let c =
+                    Repairer::pair_cost(tokens[i].1, tokens[k].1) + cost[i + 1][k] + cost[k + 1][j];
+                if c < best.0 {
+                    best = (c, Some(k));
+                }
+            }
+            cost[i][j] = best.0;
+            choice[i][j] = best.1;
+        }
+    }
+
+    let mut r = Repairer {
+        brackets,
+        tokens,
+        end: s.len(),
+        choice,
+        edits: vec![],
+    };
+    r.reconstruct(0, n);
+
+    // inserts go before anything else at the same offset, and the nested
+    // ones were recorded first
+    let mut edits = r.edits;
+    edits.sort_by_key(|e| match e {
+        Edit::Insert { offset, .. } => (*offset, 0),
+        Edit::Delete { offset, .. } | Edit::Substitute { offset, .. } => (*offset, 1),
+    });
+
+    let mut line = String::with_capacity(s.len());
+    let mut pos = 0;
+    for e in edits.iter() {
+        match e {
+            Edit::Insert { offset, text } => {
+                line.push_str(&s[pos..*offset]);
+                line.push_str(text);
+                pos = *offset;
+            }
+            Edit::Delete { offset, len } => {
+                line.push_str(&s[pos..*offset]);
+                pos = offset + len;
+            }
+            Edit::Substitute { offset, len, text } => {
+                line.push_str(&s[pos..*offset]);
+                line.push_str(text);
+                pos = offset + len;
+            }
+        }
+    }
+    line.push_str(&s[pos..]);
+
+    Ok(Repair { line, edits })
+}
+
+fn score_completion(brackets: &Brackets, x: &[Open]) -> u64 {
+    x.iter().fold(0u64, |acc, o| {
         (5 * acc)
-            + match c {
-                ')' => 1,
-                ']' => 2,
-                '}' => 3,
-                '>' => 4,
+            + match brackets.closer(o.pair) {
+                ")" => 1,
+                "]" => 2,
+                "}" => 3,
+                ">" => 4,
                 _ => unreachable!(),
             }
     })
 }
 
 pub fn solve_a() -> Result<u64> {
+    let brackets = Brackets::standard();
+
     let score = include_str!("../input")
         .lines()
         .map(|line| {
-            if let Nav::SyntaxErr(c) = check_syntax(line.trim()) {
-                match c {
-                    ')' => 3,
-                    ']' => 57,
-                    '}' => 1197,
-                    '>' => 25137,
+            if let Nav::SyntaxErr { pair, .. } = check_syntax(line.trim(), &brackets) {
+                match brackets.closer(pair) {
+                    ")" => 3,
+                    "]" => 57,
+                    "}" => 1197,
+                    ">" => 25137,
                     _ => unreachable!(),
                 }
             } else {
@@ -79,10 +569,12 @@ pub fn solve_a() -> Result<u64> {
 }
 
 pub fn solve_b() -> Result<u64> {
+    let brackets = Brackets::standard();
+
     let mut scores = include_str!("../input")
         .lines()
-        .map(|line| match check_syntax(line.trim()) {
-            Nav::Incomplete(r) => score_completion(&r),
+        .map(|line| match check_syntax(line.trim(), &brackets) {
+            Nav::Incomplete(r) => score_completion(&brackets, &r),
             _ => 0,
         })
         .filter(|&x| x > 0)
@@ -93,3 +585,168 @@ pub fn solve_b() -> Result<u64> {
 
     Ok(score)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_offsets() {
+        let brackets = Brackets::standard();
+
+        assert_eq!(
+            check_syntax("{([(<{}[<>[]}>{[]{[(<()>", &brackets),
+            Nav::SyntaxErr {
+                pair: 2,
+                offset: 12,
+                open: Some(Open { pair: 1, offset: 7 }),
+            }
+        );
+        assert_eq!(
+            check_syntax("())", &brackets),
+            Nav::SyntaxErr {
+                pair: 0,
+                offset: 2,
+                open: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let brackets = Brackets::standard();
+        let edits = |s| repair(s, &brackets).unwrap().edits.len();
+
+        assert_eq!(edits("(]"), 1);
+        assert_eq!(edits("(("), 1);
+        assert_eq!(edits(")("), 2);
+        assert_eq!(edits("[(])"), 2);
+        assert_eq!(edits("[<>({}){}[([])<>]]"), 0);
+
+        let r = repair("())", &brackets).unwrap();
+        assert_eq!(r.line, "()");
+        assert_eq!(r.edits, vec![Edit::Delete { offset: 2, len: 1 }]);
+
+        let r = repair("{([(<{}[<>[]}>{[]{[(<()>", &brackets).unwrap();
+        assert_eq!(check_syntax(&r.line, &brackets), Nav::Complete);
+        assert_eq!(r.edits.len(), 5);
+
+        // deep nesting
+        let deep = "(".repeat(200) + &")".repeat(199);
+        let r = repair(&deep, &brackets).unwrap();
+        assert_eq!(check_syntax(&r.line, &brackets), Nav::Complete);
+        assert_eq!(r.edits.len(), 1);
+
+        assert!(repair(&"()".repeat(MAX_REPAIR_TOKENS / 2 + 1), &brackets).is_err());
+
+        let brackets = Brackets::new(&[("begin", "end"), ("(", ")")]).unwrap();
+        let r = repair("begin (x end", &brackets).unwrap();
+        assert_eq!(r.line, "begin (x )end");
+        assert_eq!(
+            r.edits,
+            vec![Edit::Insert {
+                offset: 9,
+                text: ")".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_stream() {
+        let brackets = Brackets::standard();
+        let example = include_str!("../example");
+
+        // a tiny buffer splits lines over many reads
+        let reader = std::io::BufReader::with_capacity(3, example.as_bytes());
+        let streamed = check_stream(reader, &brackets)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let expected = example
+            .lines()
+            .map(|line| check_syntax(line, &brackets))
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, expected);
+
+        let brackets = Brackets::new(&[("begin", "end"), ("(", ")")]).unwrap();
+        let text = "begin f(x) end\nbegin (x end)\nbegin begin end\nbegin blend end\nbegin endless";
+        let reader = std::io::BufReader::with_capacity(2, text.as_bytes());
+        let streamed = check_stream(reader, &brackets)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let expected = text
+            .lines()
+            .map(|line| check_syntax(line, &brackets))
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, expected);
+
+        // multi-byte delimiters split across reads
+        let brackets = Brackets::new(&[("«", "»"), ("(", ")")]).unwrap();
+        let text = "(x»)\n«hello\n«(hello)»";
+        let reader = std::io::BufReader::with_capacity(1, text.as_bytes());
+        let streamed = check_stream(reader, &brackets)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let expected = text
+            .lines()
+            .map(|line| check_syntax(line, &brackets))
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, expected);
+        assert!(matches!(streamed[0], Nav::SyntaxErr { offset: 2, .. }));
+        assert!(matches!(streamed[1], Nav::Incomplete(_)));
+    }
+
+    #[test]
+    fn test_word_delimiters() {
+        let brackets = Brackets::new(&[("begin", "end"), ("(", ")")]).unwrap();
+
+        assert_eq!(check_syntax("begin f(x) end", &brackets), Nav::Complete);
+        assert_eq!(
+            check_syntax("begin (x end)", &brackets),
+            Nav::SyntaxErr {
+                pair: 0,
+                offset: 9,
+                open: Some(Open { pair: 1, offset: 6 }),
+            }
+        );
+        assert_eq!(
+            check_syntax("begin begin end", &brackets),
+            Nav::Incomplete(vec![Open { pair: 0, offset: 0 }])
+        );
+        assert!(Brackets::new(&[("", ")")]).is_err());
+
+        // word delimiters don't match inside identifiers
+        assert_eq!(
+            check_syntax("begin blend := x end", &brackets),
+            Nav::Complete
+        );
+        assert_eq!(
+            check_syntax("begin endless(x) end", &brackets),
+            Nav::Complete
+        );
+        assert_eq!(check_syntax("rebegin (x) end_", &brackets), Nav::Complete);
+        assert_eq!(
+            check_syntax("begin(end)", &brackets),
+            Nav::SyntaxErr {
+                pair: 0,
+                offset: 6,
+                open: Some(Open { pair: 1, offset: 5 }),
+            }
+        );
+
+        // non-ASCII punctuation matches right next to text
+        let guillemets = Brackets::new(&[("«", "»"), ("(", ")")]).unwrap();
+        assert_eq!(
+            check_syntax("(x»)", &guillemets),
+            Nav::SyntaxErr {
+                pair: 0,
+                offset: 2,
+                open: Some(Open { pair: 1, offset: 0 }),
+            }
+        );
+        assert_eq!(
+            check_syntax("«hello", &guillemets),
+            Nav::Incomplete(vec![Open { pair: 0, offset: 0 }])
+        );
+        assert_eq!(check_syntax("«(hello)»", &guillemets), Nav::Complete);
+    }
+}
@@ -1,12 +1,121 @@
-use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-type Grid = [u8; 100];
+/// How octopuses are connected to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// A flat grid where each cell touches the 8 around it.
+    Flat,
+    /// A flat grid whose edges wrap around to the opposite side.
+    Torus,
+    /// Hexagons in offset rows, odd rows shifted half a cell right, so each
+    /// cell touches 6 others.
+    Hex,
+    /// Layers stacked into a volume where each cell touches the 26 around it.
+    Cube,
+}
+
+/// Energy levels of a `width` x `height` x `depth` block of octopuses, stored
+/// row by row and layer by layer. Flat grids have a depth of one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub topology: Topology,
+    cells: Vec<u8>,
+}
+
+impl Grid {
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
 
-const N: usize = 10;
-const NX: isize = 10;
-const N2: u64 = (N * N) as u64;
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    pub fn with_topology(self, topology: Topology) -> Grid {
+        Grid { topology, ..self }
+    }
+
+    /// Cells touching cell `ix`, each listed once.
+    fn neighbors(&self, ix: usize, out: &mut Vec<usize>) {
+        out.clear();
+
+        let (w, h, d) = (
+            self.width as isize,
+            self.height as isize,
+            self.depth as isize,
+        );
+        let (i, j, k) = (
+            (ix % self.width) as isize,
+            (ix / self.width % self.height) as isize,
+            (ix / (self.width * self.height)) as isize,
+        );
+        let index = |i: isize, j: isize, k: isize| (i + w * (j + h * k)) as usize;
+        let inside = |i: isize, j: isize| i >= 0 && i < w && j >= 0 && j < h;
+
+        match self.topology {
+            Topology::Flat => out.extend(
+                DELTAS
+                    .iter()
+                    .map(|(di, dj)| (i + di, j + dj))
+                    .filter(|&(ni, nj)| inside(ni, nj))
+                    .map(|(ni, nj)| index(ni, nj, k)),
+            ),
+            Topology::Torus => {
+                out.extend(
+                    DELTAS
+                        .iter()
+                        .map(|(di, dj)| index((i + di).rem_euclid(w), (j + dj).rem_euclid(h), k)),
+                );
+                // narrow grids wrap onto the same cell from both sides
+                out.sort_unstable();
+                out.dedup();
+                out.retain(|&n| n != ix);
+            }
+            Topology::Hex => {
+                let deltas = if j % 2 == 0 {
+                    &HEX_EVEN_DELTAS
+                } else {
+                    &HEX_ODD_DELTAS
+                };
+                out.extend(
+                    deltas
+                        .iter()
+                        .map(|(di, dj)| (i + di, j + dj))
+                        .filter(|&(ni, nj)| inside(ni, nj))
+                        .map(|(ni, nj)| index(ni, nj, k)),
+                );
+            }
+            Topology::Cube => {
+                for dk in -1..=1 {
+                    for dj in -1..=1 {
+                        for di in -1..=1 {
+                            let (ni, nj, nk) = (i + di, j + dj, k + dk);
+                            if (di, dj, dk) != (0, 0, 0) && inside(ni, nj) && nk >= 0 && nk < d {
+                                out.push(index(ni, nj, nk));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const MAX_SYNC_STEPS: u64 = 100_000;
 
 const DELTAS: [(isize, isize); 8] = [
     (0, 1),
@@ -19,6 +128,9 @@ const DELTAS: [(isize, isize); 8] = [
     (-1, -1),
 ];
 
+const HEX_EVEN_DELTAS: [(isize, isize); 6] = [(1, 0), (-1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)];
+const HEX_ODD_DELTAS: [(isize, isize); 6] = [(1, 0), (-1, 0), (0, -1), (1, -1), (0, 1), (1, 1)];
+
 pub fn main() -> Result<()> {
     let start = std::time::Instant::now();
     let soln_a = solve_a()?;
@@ -33,40 +145,53 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-fn step(g: &mut Grid) -> u64 {
+/// A cell that flashed. Wave 0 cells flashed from their own energy gain, and
+/// wave `n + 1` cells were pushed over by flashes in wave `n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flash {
+    pub cell: usize,
+    pub wave: usize,
+}
+
+pub fn step(g: &mut Grid) -> u64 {
+    step_with(g, None)
+}
+
+/// Advances the grid one step, returning the number of flashes. If `history`
+/// is given, the flashes are appended to it in cascade order.
+pub fn step_with(g: &mut Grid, mut history: Option<&mut Vec<Flash>>) -> u64 {
     let mut nflashes = 0;
 
-    let mut visited = [false; 100];
+    let mut visited = vec![false; g.len()];
     let mut to_check = VecDeque::new();
+    let mut neighbors = vec![];
 
-    g.iter_mut().enumerate().for_each(|(ix, e)| {
+    g.cells.iter_mut().enumerate().for_each(|(ix, e)| {
         *e += 1;
         if *e > 9 {
             visited[ix] = true;
-            to_check.push_back(ix);
+            to_check.push_back((ix, 0));
         }
     });
 
     while !to_check.is_empty() {
-        if let Some(ix) = to_check.pop_front() {
-            let (j, i) = ((ix / N) as isize, (ix % N) as isize);
-
-            DELTAS
-                .iter()
-                .filter(|(di, dj)| (i + di >= 0) && (i + di < NX) && (j + dj >= 0) && (j + dj < NX))
-                .map(|(di, dj)| (i + di, j + dj))
-                .for_each(|(ni, nj)| {
-                    let nix = ni as usize + N * nj as usize;
-                    g[nix] += 1;
-                    if (g[nix] > 9) && !visited[nix] {
-                        visited[nix] = true;
-                        to_check.push_back(nix);
-                    }
-                });
+        if let Some((ix, wave)) = to_check.pop_front() {
+            if let Some(h) = history.as_mut() {
+                h.push(Flash { cell: ix, wave });
+            }
+
+            g.neighbors(ix, &mut neighbors);
+            for &nix in neighbors.iter() {
+                g.cells[nix] += 1;
+                if (g.cells[nix] > 9) && !visited[nix] {
+                    visited[nix] = true;
+                    to_check.push_back((nix, wave + 1));
+                }
+            }
         }
     }
 
-    g.iter_mut().for_each(|e| {
+    g.cells.iter_mut().for_each(|e| {
         if *e > 9 {
             nflashes += 1;
             *e = 0
@@ -76,29 +201,413 @@ fn step(g: &mut Grid) -> u64 {
     nflashes
 }
 
-fn parse_grid(s: &str) -> Grid {
-    s.lines().enumerate().fold([0; 100], |mut acc, (j, line)| {
-        for (i, c) in line.chars().enumerate() {
-            acc[i + N * j] = c.to_digit(10).unwrap() as u8;
+/// A recorded simulation: the starting grid and the flashes of every step.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub initial: Grid,
+    pub steps: Vec<Vec<Flash>>,
+}
+
+/// A snapshot for animation: the grid as a wave of `step`'s cascade fires,
+/// with the cells that have flashed so far in that step lit (and at zero).
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub step: usize,
+    pub grid: Grid,
+    pub lit: Vec<bool>,
+}
+
+/// Runs `nsteps` steps from `g`, recording the flashes.
+pub fn record(g: &Grid, nsteps: usize) -> Run {
+    let mut grid = g.clone();
+    let steps = (0..nsteps)
+        .map(|_| {
+            let mut flashes = vec![];
+            step_with(&mut grid, Some(&mut flashes));
+            flashes
+        })
+        .collect();
+
+    Run {
+        initial: g.clone(),
+        steps,
+    }
+}
+
+impl Run {
+    /// Replays the run: a frame for the starting grid, then one frame per
+    /// cascade wave of each step, or a single frame if nothing flashed. The
+    /// frame for a wave shows each cell's energy after the step's gain and
+    /// the pushes from earlier waves.
+    pub fn frames(&self) -> Vec<Frame> {
+        let mut grid = self.initial.clone();
+        let mut frames = vec![Frame {
+            step: 0,
+            lit: vec![false; grid.len()],
+            grid: grid.clone(),
+        }];
+        let mut neighbors = vec![];
+
+        for (s, flashes) in self.steps.iter().enumerate() {
+            grid.cells.iter_mut().for_each(|e| *e += 1);
+            let mut lit = vec![false; grid.len()];
+
+            let mut waves = flashes
+                .chunk_by(|a, b| a.wave == b.wave)
+                .collect::<Vec<_>>();
+            if waves.is_empty() {
+                waves.push(&[]);
+            }
+            for wave in waves {
+                for f in wave {
+                    lit[f.cell] = true;
+                }
+
+                let mut shown = grid.clone();
+                for (e, _) in shown.cells.iter_mut().zip(&lit).filter(|(_, &l)| l) {
+                    *e = 0;
+                }
+                frames.push(Frame {
+                    step: s + 1,
+                    grid: shown,
+                    lit: lit.clone(),
+                });
+
+                for f in wave {
+                    grid.neighbors(f.cell, &mut neighbors);
+                    for &nix in neighbors.iter() {
+                        grid.cells[nix] += 1;
+                    }
+                }
+            }
+
+            for (e, _) in grid.cells.iter_mut().zip(&lit).filter(|(_, &l)| l) {
+                *e = 0;
+            }
         }
-        acc
-    })
+
+        frames
+    }
+
+    /// Writes every frame to `dir` as `frame_00000.ppm`, `frame_00001.ppm`, ...
+    pub fn write_ppm_frames(&self, dir: &Path, scale: usize) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (i, frame) in self.frames().iter().enumerate() {
+            let file = std::fs::File::create(dir.join(format!("frame_{:05}.ppm", i)))?;
+            frame.write_ppm(&mut std::io::BufWriter::new(file), scale)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Frame {
+    /// Writes the frame as a binary PPM with each cell drawn as a `scale` x
+    /// `scale` square: lit cells in yellow, the rest in grey by energy. The
+    /// layers of a volume are stacked top to bottom.
+    pub fn write_ppm(&self, w: &mut impl Write, scale: usize) -> Result<()> {
+        if scale == 0 {
+            return Err(anyhow!("scale must be positive"));
+        }
+        let (width, height) = (
+            self.grid.width * scale,
+            self.grid.height * self.grid.depth * scale,
+        );
+        write!(w, "P6\n{} {}\n255\n", width, height)?;
+
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| x / scale + (y / scale) * self.grid.width))
+            .flat_map(|ix| {
+                if self.lit[ix] {
+                    [255, 230, 90]
+                } else {
+                    let v = 20 * self.grid.cells[ix];
+                    [v, v, v]
+                }
+            })
+            .collect::<Vec<_>>();
+        w.write_all(&pixels)?;
+
+        Ok(())
+    }
+}
+
+/// Draws the frame as rows of energy levels, with lit cells shown as `*` and
+/// the layers of a volume separated by blank lines.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (r, (row, lit)) in self
+            .grid
+            .cells
+            .chunks(self.grid.width.max(1))
+            .zip(self.lit.chunks(self.grid.width.max(1)))
+            .enumerate()
+        {
+            if r > 0 && r % self.grid.height == 0 {
+                writeln!(f)?;
+            }
+            for (e, l) in row.iter().zip(lit) {
+                if *l {
+                    write!(f, "*")?;
+                } else {
+                    write!(f, "{}", e)?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Outcome of searching for the first step where every octopus flashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sync {
+    /// Every octopus flashed during step `k`.
+    Synchronized(u64),
+    /// The grid after step `start` comes back every `length` steps, and
+    /// never synchronizes.
+    Cycle { start: u64, length: u64 },
+    /// Neither happened within the step budget.
+    Exhausted,
+}
+
+fn state_hash(cells: &[u8]) -> u64 {
+    let mut h = DefaultHasher::new();
+    cells.hash(&mut h);
+    h.finish()
+}
+
+/// Steps the grid until it synchronizes, repeats an earlier state, or has
+/// taken `max_steps` steps. Only a hash of each state seen is kept; when a
+/// hash comes back, the earlier state is rebuilt by replaying from `g` to
+/// rule out a collision.
+pub fn find_sync(g: &Grid, max_steps: u64) -> Sync {
+    let mut grid = g.clone();
+    let mut seen: HashMap<u64, Vec<u64>> = HashMap::new();
+    seen.insert(state_hash(&grid.cells), vec![0]);
+
+    for k in 1..=max_steps {
+        if step(&mut grid) == grid.len() as u64 {
+            return Sync::Synchronized(k);
+        }
+
+        let steps = seen.entry(state_hash(&grid.cells)).or_default();
+        for &start in steps.iter() {
+            let mut earlier = g.clone();
+            for _ in 0..start {
+                step(&mut earlier);
+            }
+            if earlier.cells == grid.cells {
+                return Sync::Cycle {
+                    start,
+                    length: k - start,
+                };
+            }
+        }
+        steps.push(k);
+    }
+
+    Sync::Exhausted
+}
+
+/// Parses rows of digits; every row must have the same length.
+pub fn parse_grid(s: &str) -> Result<Grid> {
+    let mut g = Grid {
+        width: 0,
+        height: 0,
+        depth: 1,
+        topology: Topology::Flat,
+        cells: vec![],
+    };
+
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        for c in line.chars() {
+            let e = c
+                .to_digit(10)
+                .ok_or_else(|| anyhow!("invalid energy level {:?}", c))?;
+            g.cells.push(e as u8);
+        }
+
+        if g.height == 0 {
+            g.width = g.cells.len();
+        } else if g.cells.len() != g.width * (g.height + 1) {
+            return Err(anyhow!("row {} is not {} wide", g.height, g.width));
+        }
+        g.height += 1;
+    }
+
+    Ok(g)
+}
+
+/// Parses layers of digit rows separated by blank lines into a volume with
+/// the `Cube` topology.
+pub fn parse_volume(s: &str) -> Result<Grid> {
+    let lines = s.lines().collect::<Vec<_>>();
+    let layers = lines
+        .split(|l| l.trim().is_empty())
+        .filter(|l| !l.is_empty())
+        .map(|l| parse_grid(&l.join("\n")))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut g = match layers.first() {
+        Some(first) => Grid {
+            depth: 0,
+            cells: vec![],
+            ..first.clone()
+        },
+        None => return parse_grid(""),
+    };
+    for layer in layers {
+        if (layer.width, layer.height) != (g.width, g.height) {
+            return Err(anyhow!(
+                "layer {} is {}x{}, expected {}x{}",
+                g.depth,
+                layer.width,
+                layer.height,
+                g.width,
+                g.height
+            ));
+        }
+        g.cells.extend(layer.cells);
+        g.depth += 1;
+    }
+
+    Ok(g.with_topology(Topology::Cube))
 }
 
 pub fn solve_a() -> Result<u64> {
-    let mut g = parse_grid(include_str!("../input"));
+    let mut g = parse_grid(include_str!("../input"))?;
     let flashes: u64 = (0..100).map(|_| step(&mut g)).sum();
 
     Ok(flashes)
 }
 
 pub fn solve_b() -> Result<u64> {
-    let mut g = parse_grid(include_str!("../input"));
+    let g = parse_grid(include_str!("../input"))?;
+    if g.is_empty() {
+        return Err(anyhow!("empty grid"));
+    }
+
+    match find_sync(&g, MAX_SYNC_STEPS) {
+        Sync::Synchronized(k) => Ok(k),
+        Sync::Cycle { start, length } => Err(anyhow!(
+            "grid cycles with period {} from step {} without synchronizing",
+            length,
+            start
+        )),
+        Sync::Exhausted => Err(anyhow!("no sync within {} steps", MAX_SYNC_STEPS)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_sizes() {
+        let mut g = parse_grid(include_str!("../example")).unwrap();
+        assert_eq!((g.width, g.height), (10, 10));
+        assert_eq!((0..100).map(|_| step(&mut g)).sum::<u64>(), 1656);
+
+        let mut g = parse_grid("11111\n19991\n19191\n19991\n11111").unwrap();
+        assert_eq!((g.width, g.height), (5, 5));
+        assert_eq!(step(&mut g), 9);
+        assert_eq!(
+            g.cells(),
+            parse_grid("34543\n40004\n50005\n40004\n34543")
+                .unwrap()
+                .cells()
+        );
+
+        let mut g = parse_grid("9999999999999\n9999999999999").unwrap();
+        assert_eq!((g.width, g.height), (13, 2));
+        assert_eq!(step(&mut g), 26);
 
-    let mut cnt = 1;
-    while step(&mut g) != N2 {
-        cnt += 1
+        assert!(parse_grid("123\n12").is_err());
+        assert!(parse_grid("12a").is_err());
     }
 
-    Ok(cnt)
+    #[test]
+    fn test_find_sync() {
+        let g = parse_grid(include_str!("../example")).unwrap();
+        assert_eq!(find_sync(&g, 1000), Sync::Synchronized(195));
+        assert_eq!(find_sync(&g, 194), Sync::Exhausted);
+
+        // neighbors keep each other out of phase
+        let g = parse_grid("0123456789").unwrap();
+        assert_eq!(
+            find_sync(&g, 1000),
+            Sync::Cycle {
+                start: 64,
+                length: 9
+            }
+        );
+        assert_eq!(find_sync(&g, 72), Sync::Exhausted);
+    }
+
+    #[test]
+    fn test_topologies() {
+        let g = parse_grid(include_str!("../example")).unwrap();
+        let neighbors = |g: &Grid, ix| {
+            let mut out = vec![];
+            g.neighbors(ix, &mut out);
+            out.sort_unstable();
+            out
+        };
+
+        assert_eq!(neighbors(&g, 0), vec![1, 10, 11]);
+        let torus = g.clone().with_topology(Topology::Torus);
+        assert_eq!(neighbors(&torus, 0), vec![1, 9, 10, 11, 19, 90, 91, 99]);
+        let hex = g.clone().with_topology(Topology::Hex);
+        assert_eq!(neighbors(&hex, 11), vec![1, 2, 10, 12, 21, 22]);
+        assert_eq!(neighbors(&hex, 22), vec![11, 12, 21, 23, 31, 32]);
+
+        // a single 9 in the middle of a volume pushes all 26 neighbors to 2
+        let mut v = parse_volume("000\n000\n000\n\n000\n090\n000\n\n000\n000\n000").unwrap();
+        assert_eq!((v.width, v.height, v.depth), (3, 3, 3));
+        assert_eq!(neighbors(&v, 13).len(), 26);
+        assert_eq!(step(&mut v), 1);
+        assert!(v
+            .cells()
+            .iter()
+            .enumerate()
+            .all(|(ix, &e)| e == if ix == 13 { 0 } else { 2 }));
+        assert!(parse_volume("00\n00\n\n000\n000").is_err());
+
+        // the two cells of a 2x1 torus touch once, not from both sides
+        let mut t = parse_grid("90").unwrap().with_topology(Topology::Torus);
+        assert_eq!(step(&mut t), 1);
+        assert_eq!(t.cells(), &[0, 2]);
+    }
+
+    #[test]
+    fn test_replay() {
+        let g = parse_grid("11111\n19991\n19191\n19991\n11111").unwrap();
+        let run = record(&g, 2);
+
+        // the ring flashes first, then the center it pushes over
+        let waves = run.steps[0].iter().map(|f| f.wave).collect::<Vec<_>>();
+        assert_eq!(waves, vec![0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(run.steps[0].last().unwrap().cell, 12);
+        assert!(run.steps[1].is_empty());
+
+        let frames = run.frames();
+        assert_eq!(frames.len(), 1 + 2 + 1);
+        assert_eq!(frames[0].to_string(), "11111\n19991\n19191\n19991\n11111\n");
+        assert_eq!(frames[1].to_string(), "22222\n2***2\n2*2*2\n2***2\n22222\n");
+        assert_eq!(frames[2].to_string(), "34543\n4***4\n5***5\n4***4\n34543\n");
+        assert_eq!(frames[3].to_string(), "45654\n51115\n61116\n51115\n45654\n");
+
+        // replaying ends each step where stepping does
+        let mut g = g;
+        step(&mut g);
+        step(&mut g);
+        assert_eq!(frames[3].grid.cells, g.cells);
+
+        let mut ppm = vec![];
+        frames[2].write_ppm(&mut ppm, 2).unwrap();
+        assert!(ppm.starts_with(b"P6\n10 10\n255\n"));
+        assert_eq!(ppm.len(), 13 + 10 * 10 * 3);
+    }
 }
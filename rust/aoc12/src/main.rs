@@ -26,6 +26,13 @@ enum Node {
 
 type Graph = HashMap<Node, HashSet<Node>>;
 
+/// The cave graph along with the name of every cave.
+#[derive(Debug, Clone)]
+pub struct Caves {
+    graph: Graph,
+    names: HashMap<Node, String>,
+}
+
 fn parse_line(s: &str) -> IResult<&str, (&str, &str)> {
     separated_pair(alpha1, tag("-"), alpha1)(s)
 }
@@ -39,8 +46,6 @@ fn convert_cave<'a>(
         "start" => Node::Start,
         "end" => Node::End,
         _ => {
-            let is_big = s.chars().next().unwrap().is_uppercase();
-
             let id = if let Some(id) = name2id.get(s) {
                 *id
             } else {
@@ -49,16 +54,20 @@ fn convert_cave<'a>(
                 *curr_id
             };
 
-            if is_big {
-                Node::BigCave(id)
-            } else {
-                Node::SmallCave(id)
-            }
+            cave_node(s, id)
         }
     }
 }
 
-fn build_graph(s: &str) -> Graph {
+fn cave_node(s: &str, id: usize) -> Node {
+    if s.chars().next().unwrap().is_uppercase() {
+        Node::BigCave(id)
+    } else {
+        Node::SmallCave(id)
+    }
+}
+
+pub fn build_graph(s: &str) -> Caves {
     let mut name2id = HashMap::new();
 
     let (_, g) = s.lines().fold(
@@ -76,7 +85,15 @@ fn build_graph(s: &str) -> Graph {
         },
     );
 
-    g
+    // resolve the bit ids back to names
+    let mut names = name2id
+        .into_iter()
+        .map(|(name, id)| (cave_node(name, id), name.to_string()))
+        .collect::<HashMap<_, _>>();
+    names.insert(Node::Start, "start".to_string());
+    names.insert(Node::End, "end".to_string());
+
+    Caves { graph: g, names }
 }
 
 fn dfs(g: &Graph, c: &Node, visited: usize, has_revisited: bool) -> usize {
@@ -109,17 +126,136 @@ fn dfs(g: &Graph, c: &Node, visited: usize, has_revisited: bool) -> usize {
     npaths
 }
 
+struct PathFrame<'a> {
+    node: &'a Node,
+    next: usize,
+    visited: usize,
+    has_revisited: bool,
+}
+
+/// Iterator over the paths from `start` to `end`, each given as a list of cave
+/// names.
+pub struct Paths<'a> {
+    caves: &'a Caves,
+    neighbors: HashMap<&'a Node, Vec<&'a Node>>,
+    stack: Vec<PathFrame<'a>>,
+    path: Vec<&'a str>,
+}
+
+/// Enumerates the paths counted by `dfs`, in lexicographic order of cave
+/// names, so `.take(n)` gives the first `n` paths of the sorted list. If
+/// `allow_revisit` is set, one small cave may be visited twice.
+pub fn paths(caves: &Caves, allow_revisit: bool) -> Paths<'_> {
+    let neighbors = caves
+        .graph
+        .iter()
+        .map(|(node, ns)| {
+            let mut ns = ns.iter().collect::<Vec<_>>();
+            ns.sort_unstable_by_key(|n| &caves.names[*n]);
+            (node, ns)
+        })
+        .collect();
+
+    Paths {
+        caves,
+        neighbors,
+        stack: vec![PathFrame {
+            node: &Node::Start,
+            next: 0,
+            visited: 0,
+            has_revisited: !allow_revisit,
+        }],
+        path: vec!["start"],
+    }
+}
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(top) = self.stack.last_mut() {
+            let nc = match self.neighbors.get(top.node).and_then(|ns| ns.get(top.next)) {
+                Some(nc) => *nc,
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                    continue;
+                }
+            };
+            top.next += 1;
+
+            let (visited, has_revisited) = match nc {
+                Node::End => {
+                    let mut path = self.path.clone();
+                    path.push("end");
+                    return Some(path);
+                }
+                Node::Start => continue,
+                Node::SmallCave(id) => {
+                    if top.visited & id != *id {
+                        (top.visited | id, top.has_revisited)
+                    } else if !top.has_revisited {
+                        (top.visited | id, true)
+                    } else {
+                        continue;
+                    }
+                }
+                Node::BigCave(_) => (top.visited, top.has_revisited),
+            };
+
+            self.stack.push(PathFrame {
+                node: nc,
+                next: 0,
+                visited,
+                has_revisited,
+            });
+            self.path.push(&self.caves.names[nc]);
+        }
+
+        None
+    }
+}
+
 pub fn solve_a() -> Result<usize> {
-    let g = build_graph(include_str!("../input"));
-    let npaths = dfs(&g, &Node::Start, 0, true);
+    let caves = build_graph(include_str!("../input"));
+    let npaths = dfs(&caves.graph, &Node::Start, 0, true);
 
     Ok(npaths)
 }
 
 pub fn solve_b() -> Result<usize> {
-    let g = build_graph(include_str!("../input"));
+    let caves = build_graph(include_str!("../input"));
 
-    let npaths = dfs(&g, &Node::Start, 0, false);
+    let npaths = dfs(&caves.graph, &Node::Start, 0, false);
 
     Ok(npaths)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let caves = build_graph(include_str!("../example"));
+
+        for allow_revisit in [false, true] {
+            let all = paths(&caves, allow_revisit).collect::<Vec<_>>();
+            assert_eq!(
+                all.len(),
+                dfs(&caves.graph, &Node::Start, 0, !allow_revisit)
+            );
+            assert!(all.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        let first = paths(&caves, false).take(2).collect::<Vec<_>>();
+        assert_eq!(
+            first,
+            vec![
+                vec!["start", "HN", "dc", "HN", "end"],
+                vec!["start", "HN", "dc", "HN", "kj", "HN", "end"],
+            ]
+        );
+        assert!(paths(&caves, false).any(|p| p == ["start", "kj", "dc", "end"]));
+    }
+}